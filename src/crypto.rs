@@ -0,0 +1,1250 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use aead::{Aead, KeyInit, Payload};
+use aes::Aes256;
+use aes_gcm::Aes256Gcm;
+use cbc::cipher::generic_array::GenericArray;
+use cbc::cipher::{BlockDecryptMut, BlockEncrypt, BlockEncryptMut, KeyIvInit};
+use chacha20poly1305::ChaCha20Poly1305;
+use hmac::{Hmac, Mac};
+use pbkdf2::pbkdf2_hmac;
+use rand::RngCore;
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::error::CokacencError;
+
+const MAGIC: &[u8; 8] = b"COKACENC";
+const PBKDF2_ROUNDS: u32 = 100_000;
+
+/// HMAC-SHA256 over `[header bytes][ciphertext]`, appended to the end of every
+/// CBC chunk. Verified before any decryption is attempted.
+pub type HmacSha256 = Hmac<Sha256>;
+const HMAC_TAG_SIZE: usize = 32;
+
+/// Size of a sealed DEK blob: a 32-byte data encryption key plus its 16-byte
+/// AES-256-GCM authentication tag.
+const WRAPPED_DEK_SIZE: usize = 32 + 16;
+/// On-disk size of the wrap region (`wrap_salt` + `wrap_nonce` + `wrapped_dek`)
+/// appended after a v5/v6 header's stable prefix.
+const WRAP_REGION_SIZE: usize = 16 + 12 + WRAPPED_DEK_SIZE;
+
+/// Size of a plaintext segment sealed as one AEAD frame. Keeping segments small and
+/// bounded means a corrupt/forged chunk is detected after at most one segment's worth
+/// of plaintext, rather than after the whole file has been written.
+const AEAD_SEGMENT_SIZE: usize = 64 * 1024;
+const AEAD_TAG_SIZE: usize = 16;
+
+type Aes256CbcEnc = cbc::Encryptor<Aes256>;
+type Aes256CbcDec = cbc::Decryptor<Aes256>;
+
+/// Encryption algorithm used to seal a chunk, recorded in cleartext in the chunk
+/// header so unpack knows how to read the file before anything is decrypted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum EncryptionType {
+    /// AES-256-CBC, authenticated by a trailing HMAC-SHA256 tag over the header
+    /// and ciphertext (v5 format).
+    Cbc,
+    /// AES-256-GCM, one authentication tag per 64 KiB plaintext segment.
+    #[value(name = "aes-gcm-256")]
+    AesGcm256,
+    /// ChaCha20-Poly1305, one authentication tag per 64 KiB plaintext segment.
+    #[value(name = "chacha20-poly1305")]
+    ChaCha20Poly1305,
+    /// AES-256-CTR: a stream cipher with no padding or block alignment, so
+    /// ciphertext length equals plaintext length and any byte offset can be
+    /// reached by seeking to its counter block (see [`ctr_decrypt_range`]).
+    /// Integrity carried by the same trailing HMAC-SHA256 tag as `cbc`.
+    Ctr,
+}
+
+impl EncryptionType {
+    fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            1 => Some(EncryptionType::AesGcm256),
+            2 => Some(EncryptionType::ChaCha20Poly1305),
+            _ => None,
+        }
+    }
+
+    fn tag(self) -> u8 {
+        match self {
+            EncryptionType::Cbc => 0,
+            EncryptionType::AesGcm256 => 1,
+            EncryptionType::ChaCha20Poly1305 => 2,
+            // Unused on the wire: like Cbc, Ctr is identified by chunk version
+            // (7) alone, not a tag byte — see `write_header`/`read_envelope_header`.
+            EncryptionType::Ctr => 3,
+        }
+    }
+}
+
+// ─── Key loading / derivation ──────────────────────────────────────────
+
+/// Load a key file and return its trimmed contents as password bytes.
+pub fn load_key_file(path: &Path) -> Result<Vec<u8>, CokacencError> {
+    let contents = std::fs::read_to_string(path)?;
+    Ok(contents.trim().as_bytes().to_vec())
+}
+
+pub fn generate_salt() -> [u8; 16] {
+    let mut salt = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut salt);
+    salt
+}
+
+/// IV for the CBC path.
+pub fn generate_iv() -> [u8; 16] {
+    let mut iv = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut iv);
+    iv
+}
+
+/// Base nonce for an AEAD chunk. The low 4 bytes are XORed with a per-segment
+/// counter so every 64 KiB segment in the chunk is sealed under a distinct nonce.
+pub fn generate_nonce() -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    nonce
+}
+
+/// Derive a 32-byte AES-256 key from the password via PBKDF2-HMAC-SHA512.
+pub fn derive_key(password: &[u8], salt: &[u8; 16]) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2_hmac::<Sha512>(password, salt, PBKDF2_ROUNDS, &mut key);
+    key
+}
+
+/// Derive an independent AES key and HMAC key from the same password+salt, per the
+/// classic encrypt-then-MAC construction: one PBKDF2-HMAC-SHA512 pass produces 64
+/// bytes, split into a 32-byte AES-256 key and a 32-byte HMAC-SHA256 key.
+/// Used only for the legacy (pre-envelope) v4 chunk format.
+pub fn derive_keys(password: &[u8], salt: &[u8; 16]) -> ([u8; 32], [u8; 32]) {
+    let mut out = [0u8; 64];
+    pbkdf2_hmac::<Sha512>(password, salt, PBKDF2_ROUNDS, &mut out);
+    let mut aes_key = [0u8; 32];
+    let mut mac_key = [0u8; 32];
+    aes_key.copy_from_slice(&out[..32]);
+    mac_key.copy_from_slice(&out[32..]);
+    (aes_key, mac_key)
+}
+
+/// Generate a random 32-byte data encryption key (DEK) for one file.
+pub fn generate_dek() -> [u8; 32] {
+    let mut dek = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut dek);
+    dek
+}
+
+/// Split a DEK into an AES-256 content key and an HMAC-SHA256 key.
+///
+/// Unlike [`derive_keys`], this isn't PBKDF2: the DEK is already full-entropy
+/// random key material (not a human passphrase), so a single SHA-512 hash is
+/// enough to produce two independent 32-byte halves.
+pub fn derive_dek_subkeys(dek: &[u8; 32]) -> ([u8; 32], [u8; 32]) {
+    let digest = Sha512::digest(dek);
+    let mut aes_key = [0u8; 32];
+    let mut mac_key = [0u8; 32];
+    aes_key.copy_from_slice(&digest[..32]);
+    mac_key.copy_from_slice(&digest[32..]);
+    (aes_key, mac_key)
+}
+
+/// Wrap (encrypt) a DEK under a master key via AES-256-GCM.
+pub fn wrap_dek(master_key: &[u8; 32], wrap_nonce: &[u8; 12], dek: &[u8; 32]) -> [u8; WRAPPED_DEK_SIZE] {
+    let cipher = Aes256Gcm::new(master_key.into());
+    let payload = Payload { msg: dek.as_slice(), aad: b"" };
+    let sealed = cipher.encrypt(wrap_nonce.into(), payload).expect("AEAD seal");
+    let mut out = [0u8; WRAPPED_DEK_SIZE];
+    out.copy_from_slice(&sealed);
+    out
+}
+
+/// Unwrap a DEK previously sealed by [`wrap_dek`]. Fails if `master_key` is
+/// wrong (i.e. the wrong key file) or the wrap region was tampered with.
+pub fn unwrap_dek(
+    master_key: &[u8; 32],
+    wrap_nonce: &[u8; 12],
+    wrapped_dek: &[u8; WRAPPED_DEK_SIZE],
+) -> Result<[u8; 32], CokacencError> {
+    let cipher = Aes256Gcm::new(master_key.into());
+    let payload = Payload { msg: wrapped_dek.as_slice(), aad: b"" };
+    let opened = cipher
+        .decrypt(wrap_nonce.into(), payload)
+        .map_err(|_| CokacencError::Other("DEK unwrap failed: wrong key file or corrupt header".to_string()))?;
+    let mut dek = [0u8; 32];
+    dek.copy_from_slice(&opened);
+    Ok(dek)
+}
+
+fn segment_nonce(base: &[u8; 12], counter: u32) -> [u8; 12] {
+    let mut nonce = *base;
+    let counter_bytes = counter.to_be_bytes();
+    for i in 0..4 {
+        nonce[8 + i] ^= counter_bytes[i];
+    }
+    nonce
+}
+
+/// AES-256-CTR keystream generator: encrypting or decrypting is the same XOR
+/// operation, so this is used for both directions. `base_iv` is the initial
+/// 128-bit counter block (treated as a big-endian integer); `block_counter`
+/// starts at whatever 16-byte block of the plaintext stream this instance
+/// should begin producing keystream for, which is what makes seeking to an
+/// arbitrary byte offset possible (see [`ctr_decrypt_range`]).
+struct CtrKeystream {
+    cipher: Aes256,
+    base_iv: [u8; 16],
+    block_counter: u64,
+    buf: Vec<u8>,
+}
+
+impl CtrKeystream {
+    fn new(key: &[u8; 32], base_iv: &[u8; 16], start_block: u64) -> Self {
+        CtrKeystream {
+            cipher: Aes256::new(key.into()),
+            base_iv: *base_iv,
+            block_counter: start_block,
+            buf: Vec::new(),
+        }
+    }
+
+    fn counter_block(&self, block_index: u64) -> [u8; 16] {
+        let counter = u128::from_be_bytes(self.base_iv).wrapping_add(block_index as u128);
+        counter.to_be_bytes()
+    }
+
+    /// XOR `data` with the keystream, advancing internal state by `data.len()`
+    /// plaintext/ciphertext bytes. Works for both directions since CTR mode is
+    /// its own inverse.
+    fn apply(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len());
+        let mut i = 0;
+        while i < data.len() {
+            if self.buf.is_empty() {
+                let mut block = GenericArray::clone_from_slice(&self.counter_block(self.block_counter));
+                self.cipher.encrypt_block(&mut block);
+                self.buf = block.to_vec();
+                self.block_counter += 1;
+            }
+            let take = self.buf.len().min(data.len() - i);
+            for j in 0..take {
+                out.push(data[i + j] ^ self.buf[j]);
+            }
+            self.buf.drain(..take);
+            i += take;
+        }
+        out
+    }
+}
+
+// ─── Chunk header ───────────────────────────────────────────────────────
+
+/// A per-chunk wrapped data encryption key (v5/v6 envelope formats).
+///
+/// `wrap_salt`/`wrap_nonce`/`wrapped_dek` are deliberately *not* part of
+/// [`ChunkHeader::raw`]: they sit in their own region right after the stable
+/// header prefix, so [`apply_rekey`] can overwrite just that region (rewrap
+/// the DEK under a new master key) without touching the HMAC/AEAD-AAD-covered
+/// prefix or re-encrypting any ciphertext.
+pub struct DekWrap {
+    pub wrap_salt: [u8; 16],
+    pub wrap_nonce: [u8; 12],
+    pub wrapped_dek: [u8; WRAPPED_DEK_SIZE],
+}
+
+/// Decoded `.cokacenc` chunk header (everything before the encrypted payload).
+pub struct ChunkHeader {
+    pub version: u32,
+    pub encryption_type: EncryptionType,
+    /// Content-key derivation salt for legacy (v2/v3/v4, `dek_wrap: None`) chunks.
+    /// Unused (zeroed) once a chunk carries a [`DekWrap`].
+    pub salt: [u8; 16],
+    /// CBC: the 16-byte IV. AEAD: the 12-byte nonce base, zero-padded.
+    pub iv: [u8; 16],
+    /// Ciphertext length of the sealed `[meta_len][metadata]` frame. `None` for the
+    /// CBC format, where metadata is just the start of the single CBC stream.
+    pub meta_frame_len: Option<u32>,
+    /// Whether a trailing `HMAC_TAG_SIZE`-byte HMAC-SHA256 tag follows the ciphertext
+    /// (v4/v5 Cbc, v7 Ctr). `false` for v2 Cbc (kept readable for backward compat) and for the
+    /// AEAD formats, which authenticate themselves and need no outer MAC.
+    pub has_hmac: bool,
+    /// Present for the current v5/v6 envelope formats; `None` for legacy v2/v3/v4
+    /// chunks, which derive their content key directly from the passphrase.
+    pub dek_wrap: Option<DekWrap>,
+    pub original_name: String,
+    /// Raw bytes of the header's *stable* prefix (everything up to and including
+    /// the name, excluding any wrap region). Used as AAD when sealing an AEAD
+    /// chunk's metadata frame, and as the first input to the Cbc HMAC.
+    pub raw: Vec<u8>,
+}
+
+impl ChunkHeader {
+    /// Byte offset, from the start of the chunk file, of this chunk's wrap
+    /// region. `None` for legacy chunks that don't have one.
+    fn wrap_region_offset(&self) -> Option<u64> {
+        self.dek_wrap.as_ref().map(|_| self.raw.len() as u64)
+    }
+
+    /// Total on-disk size of the header (stable prefix + wrap region, if any).
+    pub(crate) fn on_disk_len(&self) -> u64 {
+        self.raw.len() as u64 + if self.dek_wrap.is_some() { WRAP_REGION_SIZE as u64 } else { 0 }
+    }
+}
+
+/// Write a chunk header for the current envelope formats.
+///
+/// v5 (Cbc + HMAC + envelope)  : `[8B magic][4B version=5][16B content iv][2B name_len][name]`
+///                                `[16B wrap_salt][12B wrap_nonce][48B wrapped_dek]`
+/// v6 (AEAD + envelope)        : `[8B magic][4B version=6][1B enc_type][16B nonce(12)+pad(4)]`
+///                                `[4B meta_frame_len][2B name_len][name]`
+///                                `[16B wrap_salt][12B wrap_nonce][48B wrapped_dek]`
+/// v7 (Ctr + HMAC + envelope)  : same layout as v5, but `content iv` is the
+///                                initial 128-bit CTR counter block instead of
+///                                a CBC IV
+///
+/// The returned `Vec<u8>` is only the stable prefix (everything before the wrap
+/// region) — see [`ChunkHeader::raw`].
+#[allow(clippy::too_many_arguments)]
+pub fn write_header<W: Write>(
+    writer: &mut W,
+    encryption_type: EncryptionType,
+    content_iv_or_nonce: &[u8; 16],
+    meta_frame_len: Option<u32>,
+    original_name: &str,
+    wrap_salt: &[u8; 16],
+    wrap_nonce: &[u8; 12],
+    wrapped_dek: &[u8; WRAPPED_DEK_SIZE],
+) -> Result<Vec<u8>, CokacencError> {
+    let version: u32 = match encryption_type {
+        EncryptionType::Cbc => 5,
+        EncryptionType::AesGcm256 | EncryptionType::ChaCha20Poly1305 => 6,
+        EncryptionType::Ctr => 7,
+    };
+    let name_bytes = original_name.as_bytes();
+
+    let mut stable = Vec::with_capacity(48 + name_bytes.len());
+    stable.extend_from_slice(MAGIC);
+    stable.extend_from_slice(&version.to_le_bytes());
+    if !matches!(encryption_type, EncryptionType::Cbc | EncryptionType::Ctr) {
+        stable.push(encryption_type.tag());
+    }
+    stable.extend_from_slice(content_iv_or_nonce);
+    if let Some(len) = meta_frame_len {
+        stable.extend_from_slice(&len.to_le_bytes());
+    }
+    stable.extend_from_slice(&(name_bytes.len() as u16).to_le_bytes());
+    stable.extend_from_slice(name_bytes);
+
+    writer.write_all(&stable)?;
+    writer.write_all(wrap_salt)?;
+    writer.write_all(wrap_nonce)?;
+    writer.write_all(wrapped_dek)?;
+
+    Ok(stable)
+}
+
+/// Read and validate a chunk header, positioning `reader` at the start of the
+/// encrypted payload. Dispatches on version to either the legacy (v2/v3/v4,
+/// read-only) layout or the current (v5/v6/v7) envelope layout.
+pub fn read_header<R: Read>(reader: &mut R) -> Result<ChunkHeader, CokacencError> {
+    let mut raw = Vec::new();
+
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    raw.extend_from_slice(&magic);
+    if &magic != MAGIC {
+        return Err(CokacencError::Other("Bad magic: not a cokacenc chunk".to_string()));
+    }
+
+    let mut version_bytes = [0u8; 4];
+    reader.read_exact(&mut version_bytes)?;
+    raw.extend_from_slice(&version_bytes);
+    let version = u32::from_le_bytes(version_bytes);
+
+    match version {
+        2 | 3 | 4 => read_legacy_header(reader, version, raw),
+        5 | 6 | 7 => read_envelope_header(reader, version, raw),
+        other => Err(CokacencError::Other(format!("Unsupported chunk version {}", other))),
+    }
+}
+
+/// Read a v2/v3/v4 header: content key derived directly from the passphrase,
+/// no wrap region.
+fn read_legacy_header<R: Read>(reader: &mut R, version: u32, mut raw: Vec<u8>) -> Result<ChunkHeader, CokacencError> {
+    let (encryption_type, has_hmac) = match version {
+        2 => (EncryptionType::Cbc, false),
+        4 => (EncryptionType::Cbc, true),
+        3 => {
+            let mut tag = [0u8; 1];
+            reader.read_exact(&mut tag)?;
+            raw.extend_from_slice(&tag);
+            let enc_type = EncryptionType::from_tag(tag[0])
+                .ok_or_else(|| CokacencError::Other(format!("Unknown encryption type tag {}", tag[0])))?;
+            (enc_type, false)
+        }
+        _ => unreachable!("read_legacy_header is only called for versions 2/3/4"),
+    };
+
+    let mut salt = [0u8; 16];
+    reader.read_exact(&mut salt)?;
+    raw.extend_from_slice(&salt);
+
+    let mut iv = [0u8; 16];
+    reader.read_exact(&mut iv)?;
+    raw.extend_from_slice(&iv);
+
+    let meta_frame_len = if encryption_type == EncryptionType::Cbc {
+        None
+    } else {
+        let mut len_bytes = [0u8; 4];
+        reader.read_exact(&mut len_bytes)?;
+        raw.extend_from_slice(&len_bytes);
+        Some(u32::from_le_bytes(len_bytes))
+    };
+
+    let mut name_len_bytes = [0u8; 2];
+    reader.read_exact(&mut name_len_bytes)?;
+    raw.extend_from_slice(&name_len_bytes);
+    let name_len = u16::from_le_bytes(name_len_bytes) as usize;
+
+    let mut name_bytes = vec![0u8; name_len];
+    reader.read_exact(&mut name_bytes)?;
+    raw.extend_from_slice(&name_bytes);
+    let original_name = String::from_utf8_lossy(&name_bytes).to_string();
+
+    Ok(ChunkHeader {
+        version, encryption_type, salt, iv, meta_frame_len, has_hmac,
+        dek_wrap: None, original_name, raw,
+    })
+}
+
+/// Read a v5/v6/v7 header: content key comes from unwrapping a per-file DEK
+/// (see [`resolve_chunk_key`]).
+fn read_envelope_header<R: Read>(reader: &mut R, version: u32, mut raw: Vec<u8>) -> Result<ChunkHeader, CokacencError> {
+    let encryption_type = match version {
+        5 => EncryptionType::Cbc,
+        7 => EncryptionType::Ctr,
+        _ => {
+            let mut tag = [0u8; 1];
+            reader.read_exact(&mut tag)?;
+            raw.extend_from_slice(&tag);
+            EncryptionType::from_tag(tag[0])
+                .ok_or_else(|| CokacencError::Other(format!("Unknown encryption type tag {}", tag[0])))?
+        }
+    };
+
+    let mut iv = [0u8; 16];
+    reader.read_exact(&mut iv)?;
+    raw.extend_from_slice(&iv);
+
+    let meta_frame_len = if matches!(encryption_type, EncryptionType::Cbc | EncryptionType::Ctr) {
+        None
+    } else {
+        let mut len_bytes = [0u8; 4];
+        reader.read_exact(&mut len_bytes)?;
+        raw.extend_from_slice(&len_bytes);
+        Some(u32::from_le_bytes(len_bytes))
+    };
+
+    let mut name_len_bytes = [0u8; 2];
+    reader.read_exact(&mut name_len_bytes)?;
+    raw.extend_from_slice(&name_len_bytes);
+    let name_len = u16::from_le_bytes(name_len_bytes) as usize;
+
+    let mut name_bytes = vec![0u8; name_len];
+    reader.read_exact(&mut name_bytes)?;
+    raw.extend_from_slice(&name_bytes);
+    let original_name = String::from_utf8_lossy(&name_bytes).to_string();
+
+    let mut wrap_salt = [0u8; 16];
+    reader.read_exact(&mut wrap_salt)?;
+    let mut wrap_nonce = [0u8; 12];
+    reader.read_exact(&mut wrap_nonce)?;
+    let mut wrapped_dek = [0u8; WRAPPED_DEK_SIZE];
+    reader.read_exact(&mut wrapped_dek)?;
+
+    Ok(ChunkHeader {
+        version,
+        encryption_type,
+        salt: [0u8; 16],
+        iv,
+        meta_frame_len,
+        has_hmac: matches!(encryption_type, EncryptionType::Cbc | EncryptionType::Ctr),
+        dek_wrap: Some(DekWrap { wrap_salt, wrap_nonce, wrapped_dek }),
+        original_name,
+        raw,
+    })
+}
+
+/// Resolve the AES content key for a chunk, and (for Cbc and Ctr) its HMAC key.
+///
+/// For the current v5/v6/v7 envelope formats, unwraps the chunk's DEK under a
+/// master key derived from the passphrase and the chunk's `wrap_salt`. For
+/// legacy v2/v3/v4 chunks, derives the content key directly from the
+/// passphrase, as before.
+pub fn resolve_chunk_key(password: &[u8], header: &ChunkHeader) -> Result<([u8; 32], Option<[u8; 32]>), CokacencError> {
+    match &header.dek_wrap {
+        Some(wrap) => {
+            let master_key = derive_key(password, &wrap.wrap_salt);
+            let dek = unwrap_dek(&master_key, &wrap.wrap_nonce, &wrap.wrapped_dek)?;
+            if header.has_hmac {
+                let (aes_key, mac_key) = derive_dek_subkeys(&dek);
+                Ok((aes_key, Some(mac_key)))
+            } else {
+                Ok((dek, None))
+            }
+        }
+        None => {
+            if header.has_hmac {
+                let (aes_key, mac_key) = derive_keys(password, &header.salt);
+                Ok((aes_key, Some(mac_key)))
+            } else {
+                Ok((derive_key(password, &header.salt), None))
+            }
+        }
+    }
+}
+
+/// First half of a chunk rekey: unwrap a chunk's DEK under `old_password`
+/// without writing anything. Returns `None` for legacy chunks that have no
+/// wrap region (their content key is tied directly to the passphrase and
+/// can't be rotated without a full decrypt/re-encrypt).
+///
+/// Split out from [`apply_rekey`] so [`rekey_directory`](crate::rekey::rekey_directory)
+/// can validate every chunk in a run decrypts under `old_password` *before*
+/// rewrapping any of them — see that function's doc comment for why.
+pub fn unwrap_for_rekey(header: &ChunkHeader, old_password: &[u8]) -> Result<Option<[u8; 32]>, CokacencError> {
+    let Some(wrap) = &header.dek_wrap else {
+        return Ok(None);
+    };
+    let old_master_key = derive_key(old_password, &wrap.wrap_salt);
+    let dek = unwrap_dek(&old_master_key, &wrap.wrap_nonce, &wrap.wrapped_dek)?;
+    Ok(Some(dek))
+}
+
+/// Second half of a chunk rekey: wrap `dek` under a freshly derived master
+/// key (new salt + nonce) from `new_password` and overwrite just the chunk's
+/// wrap region. `header` must have a `dek_wrap` (i.e. come from a chunk where
+/// [`unwrap_for_rekey`] returned `Some`).
+pub fn apply_rekey(
+    file: &mut File,
+    header: &ChunkHeader,
+    dek: &[u8; 32],
+    new_password: &[u8],
+) -> Result<(), CokacencError> {
+    let new_wrap_salt = generate_salt();
+    let new_master_key = derive_key(new_password, &new_wrap_salt);
+    let new_wrap_nonce = generate_nonce();
+    let new_wrapped_dek = wrap_dek(&new_master_key, &new_wrap_nonce, dek);
+
+    let mut region = Vec::with_capacity(WRAP_REGION_SIZE);
+    region.extend_from_slice(&new_wrap_salt);
+    region.extend_from_slice(&new_wrap_nonce);
+    region.extend_from_slice(&new_wrapped_dek);
+
+    let offset = header
+        .wrap_region_offset()
+        .ok_or_else(|| CokacencError::Other("apply_rekey called on a chunk with no wrap region".to_string()))?;
+    file.seek(SeekFrom::Start(offset))?;
+    file.write_all(&region)?;
+    Ok(())
+}
+
+/// Recompute the HMAC-SHA256 tag over `[header bytes][ciphertext]` and compare it
+/// (constant-time) against the tag stored at the end of the chunk file, *before*
+/// any of the chunk is decrypted. Reads the chunk independently of `reader`/`decrypt_chunk_streaming`.
+pub fn verify_chunk_hmac(path: &Path, header: &ChunkHeader, mac_key: &[u8; 32]) -> Result<(), CokacencError> {
+    let total_len = std::fs::metadata(path)?.len();
+    let on_disk_header_len = header.on_disk_len();
+    if total_len < on_disk_header_len + HMAC_TAG_SIZE as u64 {
+        return Err(CokacencError::Other("Chunk is too short to contain an HMAC tag".to_string()));
+    }
+    let ciphertext_len = total_len - on_disk_header_len - HMAC_TAG_SIZE as u64;
+
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(on_disk_header_len))?;
+
+    let mut mac = HmacSha256::new_from_slice(mac_key).expect("HMAC accepts a key of any length");
+    mac.update(&header.raw);
+
+    let mut remaining = ciphertext_len;
+    let mut buf = [0u8; 64 * 1024];
+    while remaining > 0 {
+        let to_read = (buf.len() as u64).min(remaining) as usize;
+        let n = file.read(&mut buf[..to_read])?;
+        if n == 0 {
+            break;
+        }
+        mac.update(&buf[..n]);
+        remaining -= n as u64;
+    }
+
+    let mut tag = [0u8; HMAC_TAG_SIZE];
+    file.read_exact(&mut tag)?;
+
+    mac.verify_slice(&tag)
+        .map_err(|_| CokacencError::Other("HMAC verification failed: chunk header or ciphertext was tampered with".to_string()))
+}
+
+// ─── Encryption ─────────────────────────────────────────────────────────
+
+enum AeadCipher {
+    AesGcm256(Aes256Gcm),
+    ChaCha20Poly1305(ChaCha20Poly1305),
+}
+
+impl AeadCipher {
+    fn new(encryption_type: EncryptionType, key: &[u8; 32]) -> Self {
+        match encryption_type {
+            EncryptionType::AesGcm256 => AeadCipher::AesGcm256(Aes256Gcm::new(key.into())),
+            EncryptionType::ChaCha20Poly1305 => {
+                AeadCipher::ChaCha20Poly1305(ChaCha20Poly1305::new(key.into()))
+            }
+            EncryptionType::Cbc | EncryptionType::Ctr => {
+                unreachable!("AeadCipher is never constructed for Cbc/Ctr")
+            }
+        }
+    }
+
+    fn seal(&self, nonce: &[u8; 12], plaintext: &[u8], aad: &[u8]) -> Vec<u8> {
+        let payload = Payload { msg: plaintext, aad };
+        match self {
+            AeadCipher::AesGcm256(c) => c.encrypt(nonce.into(), payload).expect("AEAD seal"),
+            AeadCipher::ChaCha20Poly1305(c) => c.encrypt(nonce.into(), payload).expect("AEAD seal"),
+        }
+    }
+
+    fn open(&self, nonce: &[u8; 12], ciphertext: &[u8], aad: &[u8]) -> Result<Vec<u8>, CokacencError> {
+        let payload = Payload { msg: ciphertext, aad };
+        let result = match self {
+            AeadCipher::AesGcm256(c) => c.decrypt(nonce.into(), payload),
+            AeadCipher::ChaCha20Poly1305(c) => c.decrypt(nonce.into(), payload),
+        };
+        result.map_err(|_| CokacencError::Other("AEAD authentication failed: chunk is corrupt or tampered".to_string()))
+    }
+}
+
+/// Streaming encryptor for a single chunk's plaintext (`[meta_len][metadata][data]`).
+///
+/// For `Cbc` this is a plain AES-256-CBC stream. For the AEAD variants, the metadata
+/// frame is sealed once (see [`ChunkEncryptor::seal_metadata`]) with the chunk header
+/// bytes as associated data, and everything passed to [`ChunkEncryptor::update`]
+/// afterwards is sealed in independent `AEAD_SEGMENT_SIZE` segments using the
+/// metadata's own JSON bytes as associated data, so segments from one chunk can never
+/// be spliced into another chunk's stream undetected.
+pub enum ChunkEncryptor {
+    Cbc { enc: Aes256CbcEnc, buf: Vec<u8> },
+    Ctr { ks: CtrKeystream },
+    Aead { cipher: AeadCipher, nonce_base: [u8; 12], counter: u32, buf: Vec<u8>, aad: Vec<u8> },
+}
+
+impl ChunkEncryptor {
+    pub fn new_cbc(key: &[u8; 32], iv: &[u8; 16]) -> Self {
+        ChunkEncryptor::Cbc {
+            enc: Aes256CbcEnc::new(key.into(), iv.into()),
+            buf: Vec::new(),
+        }
+    }
+
+    /// `iv` is the initial 128-bit CTR counter block (see [`CtrKeystream`]).
+    pub fn new_ctr(key: &[u8; 32], iv: &[u8; 16]) -> Self {
+        ChunkEncryptor::Ctr { ks: CtrKeystream::new(key, iv, 0) }
+    }
+
+    pub fn new_aead(
+        encryption_type: EncryptionType,
+        key: &[u8; 32],
+        nonce_base: [u8; 12],
+        header_bytes: Vec<u8>,
+    ) -> Self {
+        ChunkEncryptor::Aead {
+            cipher: AeadCipher::new(encryption_type, key),
+            nonce_base,
+            counter: 0,
+            buf: Vec::new(),
+            aad: header_bytes,
+        }
+    }
+
+    /// Seal the `[4B meta_len][metadata JSON]` frame. For `Cbc` this is equivalent to
+    /// `update`; for AEAD it seals the frame as its own segment (counter 0, AAD = the
+    /// header bytes) and switches the AAD for subsequent `update` calls to `meta_bytes`.
+    /// Returns `(ciphertext to write, ciphertext length of this frame)`.
+    pub fn seal_metadata(&mut self, meta_len_bytes: &[u8; 4], meta_bytes: &[u8]) -> (Vec<u8>, u32) {
+        match self {
+            ChunkEncryptor::Cbc { .. } | ChunkEncryptor::Ctr { .. } => {
+                let mut out = self.update(meta_len_bytes);
+                out.extend_from_slice(&self.update(meta_bytes));
+                let len = out.len() as u32;
+                (out, len)
+            }
+            ChunkEncryptor::Aead { cipher, nonce_base, counter, aad, .. } => {
+                let mut frame = meta_len_bytes.to_vec();
+                frame.extend_from_slice(meta_bytes);
+                let nonce = segment_nonce(nonce_base, *counter);
+                let sealed = cipher.seal(&nonce, &frame, aad);
+                *counter += 1;
+                *aad = meta_bytes.to_vec();
+                let frame_ciphertext_len = (sealed.len() - AEAD_TAG_SIZE) as u32;
+                (sealed, frame_ciphertext_len)
+            }
+        }
+    }
+
+    /// Feed plaintext data (file bytes, after the metadata frame). Returns ciphertext
+    /// ready to write; segments are only emitted once a full `AEAD_SEGMENT_SIZE` has
+    /// accumulated, so the returned buffer may be empty.
+    pub fn update(&mut self, data: &[u8]) -> Vec<u8> {
+        match self {
+            ChunkEncryptor::Cbc { enc, buf } => {
+                buf.extend_from_slice(data);
+                let mut out = Vec::new();
+                while buf.len() >= 16 {
+                    let mut block = GenericArray::clone_from_slice(&buf[..16]);
+                    enc.encrypt_block_mut(&mut block);
+                    out.extend_from_slice(&block);
+                    buf.drain(..16);
+                }
+                out
+            }
+            ChunkEncryptor::Ctr { ks } => ks.apply(data),
+            ChunkEncryptor::Aead { cipher, nonce_base, counter, buf, aad } => {
+                buf.extend_from_slice(data);
+                let mut out = Vec::new();
+                while buf.len() >= AEAD_SEGMENT_SIZE {
+                    let segment: Vec<u8> = buf.drain(..AEAD_SEGMENT_SIZE).collect();
+                    let nonce = segment_nonce(nonce_base, *counter);
+                    out.extend_from_slice(&cipher.seal(&nonce, &segment, aad));
+                    *counter += 1;
+                }
+                out
+            }
+        }
+    }
+
+    /// Finish the stream: PKCS7-pad and encrypt the final CBC block, or seal the
+    /// final (possibly short, possibly empty) AEAD segment.
+    pub fn finalize(self) -> Vec<u8> {
+        match self {
+            ChunkEncryptor::Cbc { mut enc, mut buf } => {
+                let pad_len = 16 - (buf.len() % 16);
+                buf.resize(buf.len() + pad_len, pad_len as u8);
+                let mut out = Vec::new();
+                while !buf.is_empty() {
+                    let mut block = GenericArray::clone_from_slice(&buf[..16]);
+                    enc.encrypt_block_mut(&mut block);
+                    out.extend_from_slice(&block);
+                    buf.drain(..16);
+                }
+                out
+            }
+            // A stream cipher has nothing left to flush: every byte fed to `update`
+            // was already turned into ciphertext immediately.
+            ChunkEncryptor::Ctr { .. } => Vec::new(),
+            ChunkEncryptor::Aead { cipher, nonce_base, counter, buf, aad } => {
+                let nonce = segment_nonce(&nonce_base, counter);
+                cipher.seal(&nonce, &buf, &aad)
+            }
+        }
+    }
+}
+
+// ─── Decryption ─────────────────────────────────────────────────────────
+
+/// Decrypt a chunk's ciphertext (everything after the header) and write the
+/// resulting `[meta_len][metadata][data]` plaintext to `writer`.
+///
+/// For AEAD chunks, the metadata frame's tag is verified before any of its plaintext
+/// is released, and likewise for every subsequent data segment: a corrupt or forged
+/// chunk fails immediately instead of after the whole file has been written. For Cbc
+/// and Ctr chunks that carry an HMAC tag (v4/v5/v7), callers must call
+/// [`verify_chunk_hmac`] first: `total_len` is the whole chunk file length, from which
+/// this function subtracts the header and the trailing tag, and the tag itself is not
+/// verified here.
+pub fn decrypt_chunk_streaming<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    key: &[u8; 32],
+    header: &ChunkHeader,
+    total_len: u64,
+) -> Result<(), CokacencError> {
+    match header.encryption_type {
+        EncryptionType::Cbc => {
+            let tag_len = if header.has_hmac { HMAC_TAG_SIZE as u64 } else { 0 };
+            let ciphertext_len = total_len - header.on_disk_len() - tag_len;
+            cbc_decrypt_stream(reader, writer, key, &header.iv, ciphertext_len)
+        }
+        EncryptionType::Ctr => {
+            let tag_len = if header.has_hmac { HMAC_TAG_SIZE as u64 } else { 0 };
+            let ciphertext_len = total_len - header.on_disk_len() - tag_len;
+            ctr_decrypt_stream(reader, writer, key, &header.iv, ciphertext_len)
+        }
+        EncryptionType::AesGcm256 | EncryptionType::ChaCha20Poly1305 => {
+            aead_decrypt_stream(reader, writer, key, header)
+        }
+    }
+}
+
+fn ctr_decrypt_stream<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    key: &[u8; 32],
+    iv: &[u8; 16],
+    ciphertext_len: u64,
+) -> Result<(), CokacencError> {
+    let mut ks = CtrKeystream::new(key, iv, 0);
+    let mut read_buf = [0u8; 64 * 1024];
+    let mut remaining = ciphertext_len;
+    while remaining > 0 {
+        let to_read = (read_buf.len() as u64).min(remaining) as usize;
+        let n = reader.read(&mut read_buf[..to_read])?;
+        if n == 0 {
+            break;
+        }
+        writer.write_all(&ks.apply(&read_buf[..n]))?;
+        remaining -= n as u64;
+    }
+    Ok(())
+}
+
+/// Decrypt exactly `range_len` plaintext bytes starting at `range_start` within
+/// a single Ctr chunk's `[meta_len][metadata][data]` plaintext stream, without
+/// touching any ciphertext outside that range. `ciphertext_start` is the chunk
+/// file offset where the Ctr ciphertext begins (i.e. right after the header).
+///
+/// This is what makes `extract` able to serve a byte range from a large file
+/// without decrypting (or even reading) the rest of it: seeking to
+/// `range_start`'s 16-byte counter block costs one `Seek`, and only the
+/// (at most one) extra leading block needed to align to `range_start` is
+/// decrypted and discarded.
+pub fn ctr_decrypt_range(
+    file: &mut File,
+    ciphertext_start: u64,
+    key: &[u8; 32],
+    iv: &[u8; 16],
+    range_start: u64,
+    range_len: u64,
+) -> Result<Vec<u8>, CokacencError> {
+    if range_len == 0 {
+        return Ok(Vec::new());
+    }
+    let block_index = range_start / 16;
+    let intra_block = (range_start % 16) as usize;
+
+    file.seek(SeekFrom::Start(ciphertext_start + block_index * 16))?;
+    let mut ks = CtrKeystream::new(key, iv, block_index);
+
+    let total_to_read = intra_block as u64 + range_len;
+    let mut ciphertext = vec![0u8; total_to_read as usize];
+    file.read_exact(&mut ciphertext)?;
+    let plaintext = ks.apply(&ciphertext);
+    Ok(plaintext[intra_block..].to_vec())
+}
+
+fn cbc_decrypt_stream<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    key: &[u8; 32],
+    iv: &[u8; 16],
+    ciphertext_len: u64,
+) -> Result<(), CokacencError> {
+    let mut dec = Aes256CbcDec::new(key.into(), iv.into());
+    let mut read_buf = [0u8; 64 * 1024];
+    let mut pending: Vec<u8> = Vec::new();
+    let mut remaining = ciphertext_len;
+
+    while remaining > 0 {
+        let to_read = (read_buf.len() as u64).min(remaining) as usize;
+        let n = reader.read(&mut read_buf[..to_read])?;
+        if n == 0 {
+            break;
+        }
+        pending.extend_from_slice(&read_buf[..n]);
+        remaining -= n as u64;
+        // Keep the final block buffered so it can be PKCS7-unpadded once we run out.
+        while pending.len() > 16 {
+            let mut block = GenericArray::clone_from_slice(&pending[..16]);
+            dec.decrypt_block_mut(&mut block);
+            writer.write_all(&block)?;
+            pending.drain(..16);
+        }
+    }
+
+    if pending.len() != 16 {
+        return Err(CokacencError::Other("Ciphertext is not block-aligned".to_string()));
+    }
+    let mut block = GenericArray::clone_from_slice(&pending);
+    dec.decrypt_block_mut(&mut block);
+    let pad_len = *block.last().expect("block is 16 bytes") as usize;
+    if pad_len == 0 || pad_len > 16 {
+        return Err(CokacencError::Other("Invalid PKCS7 padding".to_string()));
+    }
+    writer.write_all(&block[..16 - pad_len])?;
+    Ok(())
+}
+
+fn aead_decrypt_stream<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    key: &[u8; 32],
+    header: &ChunkHeader,
+) -> Result<(), CokacencError> {
+    let cipher = AeadCipher::new(header.encryption_type, key);
+    let mut nonce_base = [0u8; 12];
+    nonce_base.copy_from_slice(&header.iv[..12]);
+
+    let meta_frame_len = header
+        .meta_frame_len
+        .ok_or_else(|| CokacencError::Other("Missing meta_frame_len for AEAD chunk".to_string()))? as usize;
+
+    // ── Segment 0: the metadata frame, authenticated against the header bytes ──
+    let mut meta_sealed = vec![0u8; meta_frame_len + AEAD_TAG_SIZE];
+    reader.read_exact(&mut meta_sealed)?;
+    let meta_frame = cipher.open(&segment_nonce(&nonce_base, 0), &meta_sealed, &header.raw)?;
+    if meta_frame.len() < 4 {
+        return Err(CokacencError::Other("Metadata frame too short".to_string()));
+    }
+    let meta_bytes = meta_frame[4..].to_vec();
+    writer.write_all(&meta_frame)?;
+
+    // ── Remaining segments: file data, authenticated against the metadata JSON ──
+    let mut counter = 1u32;
+    let mut read_buf = vec![0u8; AEAD_SEGMENT_SIZE + AEAD_TAG_SIZE];
+    loop {
+        let mut filled = 0;
+        while filled < read_buf.len() {
+            let n = reader.read(&mut read_buf[filled..])?;
+            if n == 0 {
+                break;
+            }
+            filled += n;
+        }
+        if filled == 0 {
+            break;
+        }
+        let plaintext = cipher.open(&segment_nonce(&nonce_base, counter), &read_buf[..filled], &meta_bytes)?;
+        writer.write_all(&plaintext)?;
+        counter += 1;
+        if filled < read_buf.len() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Build a v7 (Ctr) chunk exactly like `pack::pack_one_chunk` does, then
+    /// read it back through `read_header`/`resolve_chunk_key`/`verify_chunk_hmac`/
+    /// `decrypt_chunk_streaming` and `ctr_decrypt_range`, exactly like
+    /// `unpack`/`verify`/`extract` do. Regression test for a bug where
+    /// `read_envelope_header` read 4 phantom `meta_frame_len` bytes for Ctr,
+    /// corrupting every field after it and making every read path fail on any
+    /// `--encryption ctr` chunk.
+    #[test]
+    fn test_ctr_pack_and_read_round_trip() {
+        let dek = generate_dek();
+        let (aes_key, mac_key) = derive_dek_subkeys(&dek);
+        let iv = generate_iv();
+        let password = b"test-password";
+        let wrap_salt = generate_salt();
+        let master_key = derive_key(password, &wrap_salt);
+        let wrap_nonce = generate_nonce();
+        let wrapped_dek = wrap_dek(&master_key, &wrap_nonce, &dek);
+
+        let mut chunk = Vec::new();
+        let header_bytes = write_header(
+            &mut chunk,
+            EncryptionType::Ctr,
+            &iv,
+            None,
+            "example.bin",
+            &wrap_salt,
+            &wrap_nonce,
+            &wrapped_dek,
+        )
+        .unwrap();
+
+        let mut mac = HmacSha256::new_from_slice(&mac_key).unwrap();
+        mac.update(&header_bytes);
+
+        let meta_bytes = br#"{"some":"metadata"}"#.to_vec();
+        let meta_len_bytes = (meta_bytes.len() as u32).to_le_bytes();
+        let plaintext_data = b"the quick brown fox jumps over the lazy dog, 0123456789".to_vec();
+
+        let mut enc = ChunkEncryptor::new_ctr(&aes_key, &iv);
+        let (sealed_meta, _) = enc.seal_metadata(&meta_len_bytes, &meta_bytes);
+        chunk.extend_from_slice(&sealed_meta);
+        mac.update(&sealed_meta);
+
+        let encrypted_data = enc.update(&plaintext_data);
+        chunk.extend_from_slice(&encrypted_data);
+        mac.update(&encrypted_data);
+
+        let final_block = enc.finalize();
+        chunk.extend_from_slice(&final_block);
+        mac.update(&final_block);
+
+        let tag = mac.finalize().into_bytes();
+        chunk.extend_from_slice(&tag);
+
+        // ── read_header must parse the v7 layout without any phantom fields ──
+        let mut header_cursor = Cursor::new(chunk.clone());
+        let header = read_header(&mut header_cursor).unwrap();
+        assert_eq!(header.version, 7);
+        assert_eq!(header.encryption_type, EncryptionType::Ctr);
+        assert!(header.meta_frame_len.is_none());
+        assert_eq!(header.original_name, "example.bin");
+
+        let (resolved_key, resolved_mac_key) = resolve_chunk_key(password, &header).unwrap();
+        assert_eq!(resolved_key, aes_key);
+        let resolved_mac_key = resolved_mac_key.expect("Ctr chunks carry an HMAC key");
+
+        let mut suffix = [0u8; 8];
+        rand::thread_rng().fill_bytes(&mut suffix);
+        let suffix_hex: String = suffix.iter().map(|b| format!("{:02x}", b)).collect();
+        let mut path = std::env::temp_dir();
+        path.push(format!("cokacenc-ctr-roundtrip-test-{}.cokacenc", suffix_hex));
+        std::fs::write(&path, &chunk).unwrap();
+
+        verify_chunk_hmac(&path, &header, &resolved_mac_key).unwrap();
+
+        // ── decrypt_chunk_streaming, as unpack/verify use it ──
+        let mut data_cursor = Cursor::new(chunk.clone());
+        let _ = read_header(&mut data_cursor).unwrap();
+        let mut decrypted = Vec::new();
+        decrypt_chunk_streaming(&mut data_cursor, &mut decrypted, &resolved_key, &header, chunk.len() as u64).unwrap();
+        let mut expected = meta_len_bytes.to_vec();
+        expected.extend_from_slice(&meta_bytes);
+        expected.extend_from_slice(&plaintext_data);
+        assert_eq!(decrypted, expected);
+
+        // ── ctr_decrypt_range, as extract uses it: a sub-range of the data only ──
+        let ciphertext_start = header.on_disk_len();
+        let data_start_in_stream = 4 + meta_bytes.len() as u64;
+        let range_start = 10usize;
+        let range_len = 20usize;
+        let mut file = File::open(&path).unwrap();
+        let range = ctr_decrypt_range(
+            &mut file,
+            ciphertext_start,
+            &resolved_key,
+            &header.iv,
+            data_start_in_stream + range_start as u64,
+            range_len as u64,
+        )
+        .unwrap();
+        assert_eq!(range, plaintext_data[range_start..range_start + range_len]);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    /// Build and read back a v6 (AEAD) chunk for `encryption_type`, exactly like
+    /// `pack::pack_one_chunk` / `unpack`/`verify` do, then confirm that flipping a
+    /// byte in either the sealed metadata frame or the sealed data segment makes
+    /// `decrypt_chunk_streaming` return an error.
+    fn aead_round_trip_and_tamper(encryption_type: EncryptionType) {
+        let dek = generate_dek();
+        let nonce = generate_nonce();
+        let mut nonce_field = [0u8; 16];
+        nonce_field[..12].copy_from_slice(&nonce);
+        let password = b"test-password";
+        let wrap_salt = generate_salt();
+        let master_key = derive_key(password, &wrap_salt);
+        let wrap_nonce = generate_nonce();
+        let wrapped_dek = wrap_dek(&master_key, &wrap_nonce, &dek);
+
+        let meta_bytes = br#"{"some":"metadata"}"#.to_vec();
+        let meta_len_bytes = (meta_bytes.len() as u32).to_le_bytes();
+        let meta_frame_len = (meta_len_bytes.len() + meta_bytes.len()) as u32;
+        let plaintext_data = b"the quick brown fox jumps over the lazy dog, 0123456789".to_vec();
+
+        let mut chunk = Vec::new();
+        let header_bytes = write_header(
+            &mut chunk,
+            encryption_type,
+            &nonce_field,
+            Some(meta_frame_len),
+            "example.bin",
+            &wrap_salt,
+            &wrap_nonce,
+            &wrapped_dek,
+        )
+        .unwrap();
+        let header_end = chunk.len();
+        assert_eq!(chunk[..header_end], header_bytes[..]);
+
+        let mut enc = ChunkEncryptor::new_aead(encryption_type, &dek, nonce, header_bytes);
+        let (sealed_meta, _) = enc.seal_metadata(&meta_len_bytes, &meta_bytes);
+        let meta_start = chunk.len();
+        chunk.extend_from_slice(&sealed_meta);
+        let meta_end = chunk.len();
+
+        let mut encrypted_data = enc.update(&plaintext_data);
+        encrypted_data.extend_from_slice(&enc.finalize());
+        let data_start = chunk.len();
+        chunk.extend_from_slice(&encrypted_data);
+        let data_end = chunk.len();
+
+        // ── read_header + decrypt_chunk_streaming round-trip ──
+        let mut cursor = Cursor::new(chunk.clone());
+        let header = read_header(&mut cursor).unwrap();
+        assert_eq!(header.version, 6);
+        assert_eq!(header.encryption_type, encryption_type);
+        assert_eq!(header.meta_frame_len, Some(meta_frame_len));
+        assert_eq!(header.original_name, "example.bin");
+
+        let (resolved_key, resolved_mac_key) = resolve_chunk_key(password, &header).unwrap();
+        assert_eq!(resolved_key, dek);
+        assert!(resolved_mac_key.is_none(), "AEAD chunks carry no outer HMAC key");
+
+        let mut decrypted = Vec::new();
+        decrypt_chunk_streaming(&mut cursor, &mut decrypted, &resolved_key, &header, chunk.len() as u64).unwrap();
+        let mut expected = meta_len_bytes.to_vec();
+        expected.extend_from_slice(&meta_bytes);
+        expected.extend_from_slice(&plaintext_data);
+        assert_eq!(decrypted, expected);
+
+        // ── Tamper with the metadata frame: must fail before any plaintext is returned ──
+        let mut tampered_meta = chunk.clone();
+        tampered_meta[meta_start] ^= 0x01;
+        let mut cursor = Cursor::new(tampered_meta[header_end..].to_vec());
+        let mut out = Vec::new();
+        assert!(decrypt_chunk_streaming(&mut cursor, &mut out, &resolved_key, &header, (meta_end - header_end + (data_end - data_start)) as u64).is_err());
+
+        // ── Tamper with the data segment: must fail, not silently return corrupt data ──
+        let mut tampered_data = chunk.clone();
+        let last = tampered_data.len() - 1;
+        assert!(last >= data_start, "sanity: data segment is non-empty");
+        tampered_data[last] ^= 0x01;
+        let mut cursor = Cursor::new(tampered_data[header_end..].to_vec());
+        let mut out = Vec::new();
+        assert!(decrypt_chunk_streaming(&mut cursor, &mut out, &resolved_key, &header, (tampered_data.len() - header_end) as u64).is_err());
+    }
+
+    #[test]
+    fn test_aead_aes_gcm_round_trip_and_tamper_detection() {
+        aead_round_trip_and_tamper(EncryptionType::AesGcm256);
+    }
+
+    #[test]
+    fn test_aead_chacha20_round_trip_and_tamper_detection() {
+        aead_round_trip_and_tamper(EncryptionType::ChaCha20Poly1305);
+    }
+
+    /// Build and read back a v5 (Cbc + HMAC) chunk exactly like `pack::pack_one_chunk`
+    /// does, then confirm that `verify_chunk_hmac` accepts the untampered chunk and
+    /// rejects one whose header or ciphertext was flipped, *before* any decryption
+    /// is attempted.
+    #[test]
+    fn test_cbc_round_trip_and_hmac_tamper_detection() {
+        let dek = generate_dek();
+        let (aes_key, mac_key) = derive_dek_subkeys(&dek);
+        let iv = generate_iv();
+        let password = b"test-password";
+        let wrap_salt = generate_salt();
+        let master_key = derive_key(password, &wrap_salt);
+        let wrap_nonce = generate_nonce();
+        let wrapped_dek = wrap_dek(&master_key, &wrap_nonce, &dek);
+
+        let mut chunk = Vec::new();
+        let header_bytes = write_header(
+            &mut chunk,
+            EncryptionType::Cbc,
+            &iv,
+            None,
+            "example.bin",
+            &wrap_salt,
+            &wrap_nonce,
+            &wrapped_dek,
+        )
+        .unwrap();
+
+        let mut mac = HmacSha256::new_from_slice(&mac_key).unwrap();
+        mac.update(&header_bytes);
+
+        let meta_bytes = br#"{"some":"metadata"}"#.to_vec();
+        let meta_len_bytes = (meta_bytes.len() as u32).to_le_bytes();
+        let plaintext_data = b"the quick brown fox jumps over the lazy dog, 0123456789".to_vec();
+
+        let mut enc = ChunkEncryptor::new_cbc(&aes_key, &iv);
+        let (sealed_meta, _) = enc.seal_metadata(&meta_len_bytes, &meta_bytes);
+        chunk.extend_from_slice(&sealed_meta);
+        mac.update(&sealed_meta);
+
+        let encrypted_data = enc.update(&plaintext_data);
+        chunk.extend_from_slice(&encrypted_data);
+        mac.update(&encrypted_data);
+
+        let final_block = enc.finalize();
+        chunk.extend_from_slice(&final_block);
+        mac.update(&final_block);
+
+        let tag = mac.finalize().into_bytes();
+        chunk.extend_from_slice(&tag);
+
+        let mut header_cursor = Cursor::new(chunk.clone());
+        let header = read_header(&mut header_cursor).unwrap();
+        assert_eq!(header.version, 5);
+        assert_eq!(header.encryption_type, EncryptionType::Cbc);
+        assert_eq!(header.original_name, "example.bin");
+
+        let (resolved_key, resolved_mac_key) = resolve_chunk_key(password, &header).unwrap();
+        assert_eq!(resolved_key, aes_key);
+        let resolved_mac_key = resolved_mac_key.expect("Cbc chunks carry an HMAC key");
+
+        let mut suffix = [0u8; 8];
+        rand::thread_rng().fill_bytes(&mut suffix);
+        let suffix_hex: String = suffix.iter().map(|b| format!("{:02x}", b)).collect();
+        let mut path = std::env::temp_dir();
+        path.push(format!("cokacenc-cbc-roundtrip-test-{}.cokacenc", suffix_hex));
+        std::fs::write(&path, &chunk).unwrap();
+
+        // ── Untampered: verify passes, and decrypt_chunk_streaming recovers the plaintext ──
+        verify_chunk_hmac(&path, &header, &resolved_mac_key).unwrap();
+
+        let mut data_cursor = Cursor::new(chunk.clone());
+        let _ = read_header(&mut data_cursor).unwrap();
+        let mut decrypted = Vec::new();
+        decrypt_chunk_streaming(&mut data_cursor, &mut decrypted, &resolved_key, &header, chunk.len() as u64).unwrap();
+        let mut expected = meta_len_bytes.to_vec();
+        expected.extend_from_slice(&meta_bytes);
+        expected.extend_from_slice(&plaintext_data);
+        assert_eq!(decrypted, expected);
+
+        // ── Tampered header byte: verify_chunk_hmac must reject before any decryption ──
+        let mut tampered_header_bytes = header_bytes.clone();
+        let last = tampered_header_bytes.len() - 1;
+        tampered_header_bytes[last] ^= 0x01;
+        let tampered_header = ChunkHeader {
+            version: header.version,
+            encryption_type: header.encryption_type,
+            salt: header.salt,
+            iv: header.iv,
+            meta_frame_len: header.meta_frame_len,
+            has_hmac: header.has_hmac,
+            dek_wrap: Some(DekWrap { wrap_salt, wrap_nonce, wrapped_dek }),
+            original_name: header.original_name.clone(),
+            raw: tampered_header_bytes,
+        };
+        assert!(verify_chunk_hmac(&path, &tampered_header, &resolved_mac_key).is_err());
+
+        // ── Tampered ciphertext byte on disk: verify_chunk_hmac must reject it too ──
+        let mut tampered_chunk = chunk.clone();
+        let tamper_at = header.on_disk_len() as usize;
+        tampered_chunk[tamper_at] ^= 0x01;
+        let mut tampered_path = std::env::temp_dir();
+        tampered_path.push(format!("cokacenc-cbc-roundtrip-test-tampered-{}.cokacenc", suffix_hex));
+        std::fs::write(&tampered_path, &tampered_chunk).unwrap();
+        assert!(verify_chunk_hmac(&tampered_path, &header, &resolved_mac_key).is_err());
+
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&tampered_path);
+    }
+}
@@ -0,0 +1,41 @@
+use std::fmt;
+
+/// Unified error type for cokacenc's pack/unpack/generate pipelines.
+#[derive(Debug)]
+pub enum CokacencError {
+    Io(std::io::Error),
+    Other(String),
+    MetadataParse(String),
+    NoEncFiles(String),
+    MissingChunk { expected: String },
+    Md5Mismatch { expected: String, actual: String },
+    SeqOverflow(usize),
+}
+
+impl fmt::Display for CokacencError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CokacencError::Io(e) => write!(f, "I/O error: {}", e),
+            CokacencError::Other(msg) => write!(f, "{}", msg),
+            CokacencError::MetadataParse(msg) => write!(f, "Metadata parse error: {}", msg),
+            CokacencError::NoEncFiles(msg) => write!(f, "No encrypted files: {}", msg),
+            CokacencError::MissingChunk { expected } => {
+                write!(f, "Missing chunk: sequence {} was not found", expected)
+            }
+            CokacencError::Md5Mismatch { expected, actual } => {
+                write!(f, "MD5 mismatch: expected {}, got {}", expected, actual)
+            }
+            CokacencError::SeqOverflow(index) => {
+                write!(f, "Sequence index {} exceeds the maximum (zzzz)", index)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CokacencError {}
+
+impl From<std::io::Error> for CokacencError {
+    fn from(e: std::io::Error) -> Self {
+        CokacencError::Io(e)
+    }
+}
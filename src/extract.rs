@@ -0,0 +1,124 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use crate::crypto::{ctr_decrypt_range, load_key_file, read_header, resolve_chunk_key, verify_chunk_hmac, EncryptionType};
+use crate::error::CokacencError;
+use crate::naming;
+use crate::unpack::read_chunk_metadata;
+
+/// Decrypt and write only `[offset, offset+length)` of the original file
+/// `name` to `out`, without decrypting or merging the whole file.
+///
+/// Only files packed with `--encryption ctr` support this: CTR is a stream
+/// cipher with no padding or block alignment, so any byte offset can be
+/// reached by seeking to its 16-byte counter block (see [`ctr_decrypt_range`]).
+/// Chunks that don't overlap the requested range are never opened; a chunk
+/// that does overlap still has its whole-chunk HMAC verified before any of
+/// its plaintext is released, same as every other read path in this crate.
+///
+/// Finding which group owns `name` still requires decrypting chunk 0 of every
+/// group in `dir` (the on-disk chunk filename carries no filename
+/// information); once the owning group is found, only the chunks that
+/// overlap the requested range are touched.
+pub fn extract(
+    dir: &Path,
+    key_path: &Path,
+    name: &str,
+    offset: u64,
+    length: u64,
+    out: &mut dyn Write,
+) -> Result<(), CokacencError> {
+    let password = load_key_file(key_path)?;
+    let groups = naming::group_enc_files(dir)?;
+
+    let mut found: Option<(String, crate::pack::ChunkMetadata)> = None;
+    for (group_id, chunks) in &groups {
+        let Some(first) = chunks.first() else { continue };
+        if first.seq_index != 0 {
+            continue;
+        }
+        match read_chunk_metadata(&first.path, &password) {
+            Ok(meta) if meta.filename == name => {
+                found = Some((group_id.clone(), meta));
+                break;
+            }
+            _ => continue,
+        }
+    }
+
+    let (group_id, meta0) = found
+        .ok_or_else(|| CokacencError::NoEncFiles(format!("No file named '{}' found in {}", name, dir.display())))?;
+
+    let chunks = &groups[&group_id];
+    for (i, chunk) in chunks.iter().enumerate() {
+        if chunk.seq_index != i {
+            let expected_label = naming::seq_label(i)?;
+            return Err(CokacencError::MissingChunk { expected: expected_label });
+        }
+    }
+
+    let file_size = meta0.file_size;
+    let total_chunks = meta0.total_chunks;
+    let split_size = if total_chunks > 1 { meta0.chunk_data_size } else { file_size.max(1) };
+
+    if offset > file_size {
+        return Err(CokacencError::Other(format!(
+            "Requested offset {} is beyond the end of file ({} bytes)",
+            offset, file_size
+        )));
+    }
+    let end_byte = (offset + length).min(file_size);
+    if end_byte <= offset {
+        return Ok(());
+    }
+
+    let start_idx = (offset / split_size) as usize;
+    let end_idx = ((end_byte - 1) / split_size) as usize;
+
+    if end_idx >= chunks.len() {
+        let expected_label = naming::seq_label(end_idx)?;
+        return Err(CokacencError::MissingChunk { expected: expected_label });
+    }
+
+    for idx in start_idx..=end_idx {
+        let chunk_offset = idx as u64 * split_size;
+        let chunk_data_size = split_size.min(file_size - chunk_offset);
+        let chunk_end = chunk_offset + chunk_data_size;
+
+        let local_start = offset.max(chunk_offset) - chunk_offset;
+        let local_end = end_byte.min(chunk_end) - chunk_offset;
+        if local_end <= local_start {
+            continue;
+        }
+
+        let chunk_info = &chunks[idx];
+        let mut file = File::open(&chunk_info.path)?;
+        let header = read_header(&mut file)?;
+
+        if header.encryption_type != EncryptionType::Ctr {
+            return Err(CokacencError::Other(format!(
+                "extract only supports files packed with --encryption ctr; '{}' was packed with {:?}",
+                name, header.encryption_type
+            )));
+        }
+
+        let (key, mac_key) = resolve_chunk_key(&password, &header)?;
+        if let Some(mac_key) = mac_key {
+            verify_chunk_hmac(&chunk_info.path, &header, &mac_key)?;
+        }
+
+        let ciphertext_start = header.on_disk_len();
+        let mut meta_len_bytes = [0u8; 4];
+        meta_len_bytes.copy_from_slice(&ctr_decrypt_range(&mut file, ciphertext_start, &key, &header.iv, 0, 4)?);
+        let meta_len = u32::from_le_bytes(meta_len_bytes) as u64;
+        let data_start_in_stream = 4 + meta_len;
+
+        let plaintext_offset = data_start_in_stream + local_start;
+        let plaintext_len = local_end - local_start;
+        let decrypted = ctr_decrypt_range(&mut file, ciphertext_start, &key, &header.iv, plaintext_offset, plaintext_len)?;
+        out.write_all(&decrypted)?;
+    }
+
+    Ok(())
+}
@@ -2,8 +2,11 @@
 
 mod crypto;
 mod error;
+mod extract;
 mod naming;
 mod pack;
+mod pool;
+mod rekey;
 mod unpack;
 
 use std::path::PathBuf;
@@ -11,13 +14,15 @@ use std::path::PathBuf;
 use clap::{Parser, Subcommand};
 use rand::RngCore;
 
+use crypto::EncryptionType;
+
 #[derive(Parser)]
 #[command(
     name = "cokacenc",
     version,
     about = "AES-256-CBC file encryption + split tool",
     long_about = "\
-AES-256-CBC file encryption + split tool (v2 format)
+AES-256-CBC file encryption + split tool
 
 cokacenc encrypts files in a directory using AES-256-CBC and
 optionally splits them into chunks of a specified size.
@@ -32,23 +37,53 @@ Uses 2-pass processing with metadata embedded in each chunk.
 
 ━━━ Encryption Details ━━━
 
-  Algorithm       : AES-256-CBC (PKCS7 padding)
-  Key derivation  : PBKDF2-HMAC-SHA512, 100,000 iterations
-  Salt/IV         : Independent 16-byte random per chunk
-  Integrity check : Full MD5 hash (embedded in chunk metadata, optional with --md5)
+  Algorithm       : AES-256-CBC (default, PKCS7 padding) or, with --encryption,
+                    AES-256-GCM / ChaCha20-Poly1305 (AEAD) / AES-256-CTR (seekable
+                    stream cipher, selected per pack run)
+  Key derivation  : Envelope encryption — each file gets a fresh random 32-byte
+                    data encryption key (DEK); every chunk wraps its own copy of
+                    that DEK (AES-256-GCM) under a master key derived from the
+                    passphrase via PBKDF2-HMAC-SHA512 (100,000 iterations) and a
+                    per-chunk salt. Rotating the passphrase (`rekey`) only ever
+                    rewraps these small per-chunk blobs, never the payload.
+  Salt/IV/nonce   : Independent random per chunk
+  Integrity check : --encryption cbc chunks are authenticated end-to-end by an
+                    encrypt-then-MAC HMAC-SHA256 tag covering the header and
+                    ciphertext (verified before any decryption), on top of the
+                    optional whole-file MD5 (--md5); AEAD chunks authenticate
+                    every 64 KiB plaintext segment instead, so tampering is
+                    caught immediately
 
   → Each chunk contains full file metadata (name, size, MD5, permissions, mtime).
   → Each chunk can be decrypted independently.
 
-━━━ Chunk File Format (44-byte header + ciphertext) ━━━
-
-  Header    : [8B magic \"COKACENC\"][4B version LE (=2)][16B PBKDF2 salt][16B AES IV]
-  Plaintext : [4B meta_len LE u32][metadata JSON][file data...]
+━━━ Chunk File Format ━━━
+
+  v2 (cbc, legacy) : [8B magic \"COKACENC\"][4B version LE (=2)][16B PBKDF2 salt][16B AES IV]
+                     [2B name_len][name][4B meta_len LE u32][metadata JSON][file data...]
+                     (CBC stream, read-only backward compat, no HMAC, no envelope)
+  v3 (AEAD, legacy): as v2, plus [1B encryption type][4B meta frame ciphertext len], where
+                     the metadata frame and each 64 KiB data segment are independently
+                     sealed AEAD frames (ciphertext + 16B tag), the metadata JSON itself
+                     used as associated data for the data segments (read-only, no envelope)
+  v4 (cbc, legacy) : same layout as v2, plus a trailing 32B HMAC-SHA256 tag over the
+                     header and the whole CBC ciphertext (read-only, no envelope)
+  v5 (cbc, default): [8B magic][4B version=5][16B content IV][2B name_len][name]
+                     [16B wrap_salt][12B wrap_nonce][48B wrapped DEK][ciphertext][32B HMAC tag]
+                     — the HMAC covers the header up to and including `name`, plus the
+                     ciphertext, but *not* the wrap region, so `rekey` can overwrite it in place
+  v6 (AEAD)        : as v5, plus [1B encryption type][4B meta frame ciphertext len] before
+                     name_len, and the AEAD metadata/data frames as in v3
+  v7 (ctr)         : same layout as v5, but the content IV is the initial 128-bit CTR
+                     counter block; ciphertext length equals plaintext length, so any
+                     byte offset can be reached by seeking (see `extract`)
 
 ━━━ Output Filename Convention (v2) ━━━
 
-  <group_id 16hex>_<seq 4letter>.cokacenc
-  group_id = 8 random bytes (16 hex chars), seq = aaaa, aaab, ... zzzz (max 456,976)
+  <key_prefix 4hex>_<group_id 16hex>_<seq 4letter>.cokacenc
+  key_prefix = first 4 hex chars of MD5(key file contents), so chunks made with
+               different keys can be told apart by name (grouping uses group_id only)
+  group_id   = 8 random bytes (16 hex chars), seq = aaaa, aaab, ... zzzz (max 456,976)
   Original filename is stored inside encrypted metadata, not in the filename.
 
 ━━━ Key File ━━━
@@ -67,14 +102,40 @@ Uses 2-pass processing with metadata embedded in each chunk.
   # Encrypt with 500MB chunk split
   cokacenc pack --dir ./data --key secret.key --size 500
 
+  # Encrypt with authenticated encryption (AES-256-GCM)
+  cokacenc pack --dir ./data --key secret.key --encryption aes-gcm-256
+
+  # Encrypt a whole directory tree, preserving subdirectory paths
+  cokacenc pack --dir ./data --key secret.key --recursive
+
+  # Encrypt using 4 worker threads
+  cokacenc pack --dir ./data --key secret.key --jobs 4
+
   # Decrypt encrypted files
   cokacenc unpack --dir ./data --key secret.key
 
+  # Audit a directory without extracting anything
+  cokacenc verify --dir ./data --key secret.key
+
+  # Rotate the passphrase without re-encrypting any payload
+  cokacenc rekey --dir ./data --old-key secret.key --new-key new-secret.key
+
+  # Pack with a seekable cipher, then decrypt only a byte range of one file
+  cokacenc pack --dir ./data --key secret.key --encryption ctr
+  cokacenc extract --dir ./data --key secret.key --name video.mp4 --offset 1000000 --length 4096 > chunk.bin
+
 ━━━ Notes ━━━
 
   - With --delete, original files are removed after successful pack.
   - With --delete, .cokacenc files are removed after successful unpack.
   - Hidden files (starting with .) and .cokacenc files are excluded from pack.
+  - With --recursive, hidden subdirectories are also skipped.
+  - With --recursive, unpack recreates the original subdirectory structure
+    under --dir, creating intermediate directories as needed.
+  - --jobs controls worker-thread concurrency for pack/unpack; --jobs 1
+    (the default) is fully serial.
+  - extract only works on files packed with --encryption ctr, and writes
+    the decrypted byte range directly to stdout.
   - The same key file must be used for both pack and unpack.
   - v2 format is NOT compatible with v1 encrypted files."
 )]
@@ -85,7 +146,7 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Encrypt and split files in a directory (v2 format)
+    /// Encrypt and split files in a directory
     ///
     /// Encrypts all regular files in the specified directory using AES-256-CBC.
     /// Files exceeding --size are automatically split into multiple chunks.
@@ -98,8 +159,9 @@ enum Commands {
     ///   3. With --delete, remove the original file
     ///
     /// Output filenames:
-    ///   <group_id 16hex>_<seq 4letter>.cokacenc  (seq: aaaa~zzzz)
-    ///   Original filename is stored inside encrypted metadata.
+    ///   <key_prefix 4hex>_<group_id 16hex>_<seq 4letter>.cokacenc  (seq: aaaa~zzzz)
+    ///   key_prefix = first 4 hex chars of MD5(key file contents); original
+    ///   filename is stored inside encrypted metadata, not in the filename.
     ///
     /// Excluded from processing:
     ///   - Hidden files (starting with .)
@@ -112,8 +174,9 @@ enum Commands {
         /// Directory path containing files to encrypt
         ///
         /// All regular files in this directory will be encrypted.
-        /// Subdirectories are not traversed.
-        /// Encrypted .cokacenc files are created in the same directory.
+        /// Subdirectories are only traversed with --recursive.
+        /// Encrypted .cokacenc files are created directly in this directory
+        /// regardless of where the original file lived.
         #[arg(long, value_name = "PATH")]
         dir: PathBuf,
 
@@ -146,6 +209,35 @@ enum Commands {
         /// Without this option, MD5 computation is skipped for faster encryption.
         #[arg(long)]
         md5: bool,
+
+        /// Per-chunk encryption algorithm
+        ///
+        /// `cbc` (default) is authenticated by an encrypt-then-MAC HMAC-SHA256 tag
+        /// over the header and ciphertext, verified before any decryption is
+        /// attempted. `aes-gcm-256` and `chacha20-poly1305` seal each chunk as a sequence
+        /// of authenticated 64 KiB segments, so tampering is detected before any
+        /// plaintext is released rather than only after the whole file is decrypted.
+        /// `ctr` is a seekable stream cipher (no padding/segments); it's required
+        /// for the `extract` subcommand's random-access partial decryption.
+        #[arg(long, value_enum, default_value = "cbc")]
+        encryption: EncryptionType,
+
+        /// Recurse into subdirectories
+        ///
+        /// When specified, files in subdirectories of --dir are packed too, with
+        /// their path relative to --dir (using `/` as the separator) stored as the
+        /// original filename, so unpack recreates the same directory tree.
+        /// Without this option, only files directly inside --dir are packed.
+        #[arg(long)]
+        recursive: bool,
+
+        /// Number of worker threads for concurrent encryption
+        ///
+        /// With more than one file to pack, files are encrypted across up to this
+        /// many threads; packing a single (possibly split) file instead spreads
+        /// its independent chunks across them. Default is 1 (fully serial).
+        #[arg(long, default_value = "1", value_name = "N")]
+        jobs: usize,
     },
 
     /// Generate a random key file
@@ -181,7 +273,7 @@ enum Commands {
         force: bool,
     },
 
-    /// Decrypt and merge .cokacenc files in a directory (v2 format)
+    /// Decrypt and merge .cokacenc files in a directory
     ///
     /// Decrypts .cokacenc files in the specified directory and restores the original files.
     /// Original filename, permissions, and mtime are restored from embedded metadata.
@@ -222,6 +314,106 @@ enum Commands {
         /// verification succeed. Without this option, .cokacenc files are kept as-is.
         #[arg(long)]
         delete: bool,
+
+        /// Number of worker threads for concurrent decryption
+        ///
+        /// Independent original-file groups are decrypted and merged across up to
+        /// this many threads; chunks within a single group always stay ordered.
+        /// Default is 1 (fully serial).
+        #[arg(long, default_value = "1", value_name = "N")]
+        jobs: usize,
+    },
+
+    /// Audit .cokacenc files in a directory without extracting anything
+    ///
+    /// For each original-file group, checks (1) that the sequence of chunks
+    /// (aaaa, aaab, ...) has no gaps and ends in exactly one terminal chunk,
+    /// (2) that every chunk decrypts and authenticates (HMAC for --encryption
+    /// cbc and ctr, per-segment AEAD tags otherwise), and (3) that the merged plaintext
+    /// matches the embedded whole-file MD5, if one was recorded.
+    ///
+    /// Unlike unpack, no output files are written and a failing group does not
+    /// stop the run: every group is checked so a single pass reports all
+    /// problems, with a per-chunk PASS/FAIL/MISSING line and a final summary.
+    ///
+    /// Examples:
+    ///   cokacenc verify --dir ./mydir --key secret.key
+    Verify {
+        /// Directory path containing .cokacenc files
+        #[arg(long, value_name = "PATH")]
+        dir: PathBuf,
+
+        /// Key file path (used as password)
+        ///
+        /// Must be the same key file used during pack.
+        #[arg(long, value_name = "FILE")]
+        key: PathBuf,
+    },
+
+    /// Rotate the passphrase for every .cokacenc file in a directory
+    ///
+    /// Each chunk's data encryption key (DEK) is unwrapped with --old-key and
+    /// re-wrapped with --new-key; only the chunk's small wrap region is
+    /// rewritten, never the (potentially huge) ciphertext payload. This makes
+    /// rotating the master passphrase O(number of chunks) instead of a full
+    /// unpack + pack.
+    ///
+    /// Chunks written in a pre-envelope format (from before this feature
+    /// existed) have no wrap region to rotate and are left untouched; they
+    /// remain readable with --old-key until repacked.
+    ///
+    /// Examples:
+    ///   cokacenc rekey --dir ./mydir --old-key old.key --new-key new.key
+    Rekey {
+        /// Directory path containing .cokacenc files
+        #[arg(long, value_name = "PATH")]
+        dir: PathBuf,
+
+        /// Current key file (used to unwrap each chunk's DEK)
+        #[arg(long, value_name = "FILE")]
+        old_key: PathBuf,
+
+        /// New key file (used to re-wrap each chunk's DEK)
+        #[arg(long, value_name = "FILE")]
+        new_key: PathBuf,
+    },
+
+    /// Decrypt just a byte range of one file, written to stdout
+    ///
+    /// Reads only the chunk(s) overlapping [--offset, --offset + --length) and
+    /// decrypts only that sub-range, without decrypting or merging the whole
+    /// file. Requires the file to have been packed with `--encryption ctr`,
+    /// since only that cipher is a seekable stream with no padding or segment
+    /// framing to get in the way of random access.
+    ///
+    /// Finding the group that owns --name still requires decrypting chunk 0 of
+    /// every group in --dir; once found, chunks outside the requested range
+    /// are never opened.
+    ///
+    /// Examples:
+    ///   cokacenc extract --dir ./mydir --key secret.key --name video.mp4 --offset 1000000 --length 4096
+    Extract {
+        /// Directory path containing .cokacenc files
+        #[arg(long, value_name = "PATH")]
+        dir: PathBuf,
+
+        /// Key file path (used as password)
+        ///
+        /// Must be the same key file used during pack.
+        #[arg(long, value_name = "FILE")]
+        key: PathBuf,
+
+        /// Original filename to extract from, as recorded in chunk metadata
+        #[arg(long, value_name = "NAME")]
+        name: String,
+
+        /// Byte offset into the original file to start reading from
+        #[arg(long, default_value = "0", value_name = "BYTES")]
+        offset: u64,
+
+        /// Number of bytes to decrypt and write to stdout
+        #[arg(long, value_name = "BYTES")]
+        length: u64,
     },
 }
 
@@ -235,13 +427,21 @@ fn main() {
             size,
             delete,
             md5,
-        } => pack::pack_directory(&dir, &key, size, delete, md5),
+            encryption,
+            recursive,
+            jobs,
+        } => pack::pack_directory(&dir, &key, size, delete, md5, encryption, recursive, jobs),
         Commands::Generate {
             output,
             length,
             force,
         } => generate_key(&output, length, force),
-        Commands::Unpack { dir, key, delete } => unpack::unpack_directory(&dir, &key, delete),
+        Commands::Unpack { dir, key, delete, jobs } => unpack::unpack_directory(&dir, &key, delete, jobs),
+        Commands::Verify { dir, key } => unpack::verify_directory(&dir, &key),
+        Commands::Rekey { dir, old_key, new_key } => rekey::rekey_directory(&dir, &old_key, &new_key),
+        Commands::Extract { dir, key, name, offset, length } => {
+            extract::extract(&dir, &key, &name, offset, length, &mut std::io::stdout())
+        }
     };
 
     if let Err(e) = result {
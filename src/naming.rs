@@ -2,17 +2,12 @@ use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 
 use md5::{Digest, Md5};
+use rand::RngCore;
 
 use crate::error::CokacencError;
 
 pub const EXT: &str = ".cokacenc";
 
-/// Compute the first 5 hex chars of MD5(filename).
-pub fn filename_md5_prefix(name: &str) -> String {
-    let hash = Md5::digest(name.as_bytes());
-    format!("{:032x}", hash)[..5].to_string()
-}
-
 /// Convert index to four-letter sequence label: 0→"aaaa", max 456975→"zzzz".
 pub fn seq_label(index: usize) -> Result<String, CokacencError> {
     if index > 456_975 {
@@ -41,151 +36,90 @@ fn parse_seq_label(s: &str) -> Option<usize> {
     Some(a * 26 * 26 * 26 + b * 26 * 26 + c * 26 + d)
 }
 
-/// Temporary chunk name during pack (before content MD5 is known).
-/// Format: `<fnmd5_5>.SPLTD.TEMP.<seq>.<original_name>.cokacenc`
-pub fn temp_chunk_name(dir: &Path, original_name: &str, seq: usize) -> Result<PathBuf, CokacencError> {
-    let label = seq_label(seq)?;
-    let fnmd5 = filename_md5_prefix(original_name);
-    Ok(dir.join(format!("{}.SPLTD.TEMP.{}.{}{}", fnmd5, label, original_name, EXT)))
+/// Generate a new random group id: 8 random bytes rendered as 16 lowercase hex chars.
+pub fn generate_group_id() -> String {
+    let mut raw = [0u8; 8];
+    rand::thread_rng().fill_bytes(&mut raw);
+    raw.iter().map(|b| format!("{:02x}", b)).collect()
 }
 
-/// Temporary name for single-file encryption (before content MD5 is known).
-/// Format: `<fnmd5_5>.TEMP.<original_name>.cokacenc`
-pub fn temp_single_name(dir: &Path, original_name: &str) -> PathBuf {
-    let fnmd5 = filename_md5_prefix(original_name);
-    dir.join(format!("{}.TEMP.{}{}", fnmd5, original_name, EXT))
+/// Short, non-secret fingerprint of the key material (first 4 hex chars of its MD5).
+///
+/// Embedded in chunk filenames so chunks produced under different key files can be
+/// told apart by name. [`group_enc_files`] groups by group id alone and does not
+/// separate chunks whose group ids collide under different key prefixes.
+pub fn key_prefix(password: &[u8]) -> String {
+    let hash = Md5::digest(password);
+    format!("{:032x}", hash)[..4].to_string()
 }
 
-/// Final chunk name with content MD5 prefix.
-/// Format: `<fnmd5_5>.SPLTD.<content_md5_8>.<seq>.<original_name>.cokacenc`
-pub fn final_chunk_name(
+/// Does any chunk for `group_id` already exist in `dir`?
+pub fn group_id_exists(dir: &Path, group_id: &str) -> bool {
+    let needle = format!("_{}_", group_id);
+    match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .any(|e| e.file_name().to_string_lossy().contains(&needle)),
+        Err(_) => false,
+    }
+}
+
+/// Build the on-disk chunk filename: `<key_prefix>_<group_id>_<seq>.cokacenc`.
+///
+/// The original filename is never stored here; it lives only inside the
+/// encrypted per-chunk metadata.
+pub fn chunk_filename(
     dir: &Path,
-    original_name: &str,
-    md5_hex: &str,
+    key_prefix: &str,
+    group_id: &str,
     seq: usize,
 ) -> Result<PathBuf, CokacencError> {
     let label = seq_label(seq)?;
-    let fnmd5 = filename_md5_prefix(original_name);
-    let md5_prefix = &md5_hex[..8.min(md5_hex.len())];
-    Ok(dir.join(format!(
-        "{}.SPLTD.{}.{}.{}{}",
-        fnmd5, md5_prefix, label, original_name, EXT
-    )))
+    Ok(dir.join(format!("{}_{}_{}{}", key_prefix, group_id, label, EXT)))
 }
 
-/// Final single-file encrypted name.
-/// Format: `<fnmd5_5>.<content_md5_8>.<original_name>.cokacenc`
-pub fn single_file_enc_name(dir: &Path, original_name: &str, md5_hex: &str) -> PathBuf {
-    let fnmd5 = filename_md5_prefix(original_name);
-    let md5_prefix = &md5_hex[..8.min(md5_hex.len())];
-    dir.join(format!("{}.{}.{}{}", fnmd5, md5_prefix, original_name, EXT))
-}
-
-/// Parsed info from a .cokacenc filename.
+/// Parsed info from a `.cokacenc` filename.
 #[derive(Debug, Clone)]
 pub struct EncFileInfo {
-    pub original_name: String,
-    pub is_split: bool,
-    pub md5_fragment: String, // 8-char content MD5 prefix
-    pub seq_index: Option<usize>,
+    pub key_prefix: String,
+    pub group_id: String,
+    pub seq_index: usize,
     pub path: PathBuf,
 }
 
-/// Parse a .cokacenc filename into its components.
+/// Parse a `.cokacenc` filename into its components.
 ///
-/// Single format: `<fnmd5_5>.<content_md5_8>.<original_name>.cokacenc`
-/// Split format:  `<fnmd5_5>.SPLTD.<content_md5_8>.<seq>.<original_name>.cokacenc`
+/// Format: `<key_prefix 4hex>_<group_id 16hex>_<seq 4letter>.cokacenc`
 pub fn parse_enc_filename(path: &Path) -> Option<EncFileInfo> {
     let filename = path.file_name()?.to_str()?;
-    if !filename.ends_with(EXT) {
-        return None;
-    }
-    // Remove .cokacenc suffix
-    let base = &filename[..filename.len() - EXT.len()];
+    let base = filename.strip_suffix(EXT)?;
 
-    // Both formats start with 5 hex chars (fnmd5) followed by a dot
-    if base.len() < 6 {
-        return None;
-    }
-    let fnmd5_part = &base[..5];
-    if !fnmd5_part.chars().all(|c| c.is_ascii_hexdigit()) {
-        return None;
-    }
-    if base.as_bytes()[5] != b'.' {
+    let mut parts = base.split('_');
+    let key_prefix = parts.next()?;
+    let group_id = parts.next()?;
+    let seq_str = parts.next()?;
+    if parts.next().is_some() {
         return None;
     }
-    let after_fnmd5 = &base[6..]; // after "<fnmd5>."
-
-    // Try split format: SPLTD.<content_md5_8>.<seq>.<original_name>
-    if let Some(rest) = after_fnmd5.strip_prefix("SPLTD.") {
-        // rest = "<content_md5_8>.<seq>.<original_name>"
-        if rest.len() < 14 {
-            return None;
-        }
-        let md5_fragment = &rest[..8];
-        if !md5_fragment.chars().all(|c| c.is_ascii_hexdigit()) {
-            return None;
-        }
-        if rest.as_bytes()[8] != b'.' {
-            return None;
-        }
-        let seq_str = &rest[9..13];
-        let seq_index = parse_seq_label(seq_str)?;
-        if rest.as_bytes()[13] != b'.' {
-            return None;
-        }
-        let original_name = &rest[14..];
-        if original_name.is_empty() {
-            return None;
-        }
-
-        let expected_fnmd5 = filename_md5_prefix(original_name);
-        if fnmd5_part != expected_fnmd5 {
-            return None;
-        }
-
-        return Some(EncFileInfo {
-            original_name: original_name.to_string(),
-            is_split: true,
-            md5_fragment: md5_fragment.to_string(),
-            seq_index: Some(seq_index),
-            path: path.to_path_buf(),
-        });
-    }
 
-    // Try single format: <content_md5_8>.<original_name>
-    if after_fnmd5.len() < 10 {
-        // 8 + "." + at least 1 char
+    if key_prefix.len() != 4 || !key_prefix.chars().all(|c| c.is_ascii_hexdigit()) {
         return None;
     }
-    let md5_part = &after_fnmd5[..8];
-    if !md5_part.chars().all(|c| c.is_ascii_hexdigit()) {
-        return None;
-    }
-    if after_fnmd5.as_bytes()[8] != b'.' {
-        return None;
-    }
-    let original_name = &after_fnmd5[9..];
-    if original_name.is_empty() {
-        return None;
-    }
-
-    let expected_fnmd5 = filename_md5_prefix(original_name);
-    if fnmd5_part != expected_fnmd5 {
+    if group_id.len() != 16 || !group_id.chars().all(|c| c.is_ascii_hexdigit()) {
         return None;
     }
+    let seq_index = parse_seq_label(seq_str)?;
 
     Some(EncFileInfo {
-        original_name: original_name.to_string(),
-        is_split: false,
-        md5_fragment: md5_part.to_string(),
-        seq_index: None,
+        key_prefix: key_prefix.to_string(),
+        group_id: group_id.to_string(),
+        seq_index,
         path: path.to_path_buf(),
     })
 }
 
-/// Group .cokacenc files in a directory by their original filename.
-/// Returns a map: original_name → sorted list of EncFileInfo.
+/// Group `.cokacenc` files in a directory by their group id.
+/// Returns a map: group_id → chunks sorted by `seq_index`.
 pub fn group_enc_files(dir: &Path) -> Result<BTreeMap<String, Vec<EncFileInfo>>, CokacencError> {
     let mut groups: BTreeMap<String, Vec<EncFileInfo>> = BTreeMap::new();
 
@@ -196,16 +130,12 @@ pub fn group_enc_files(dir: &Path) -> Result<BTreeMap<String, Vec<EncFileInfo>>,
             continue;
         }
         if let Some(info) = parse_enc_filename(&path) {
-            groups
-                .entry(info.original_name.clone())
-                .or_default()
-                .push(info);
+            groups.entry(info.group_id.clone()).or_default().push(info);
         }
     }
 
-    // Sort each group by seq_index (None = single file, Some(n) = split chunk)
     for files in groups.values_mut() {
-        files.sort_by_key(|f| f.seq_index.unwrap_or(0));
+        files.sort_by_key(|f| f.seq_index);
     }
 
     Ok(groups)
@@ -240,58 +170,52 @@ mod tests {
     }
 
     #[test]
-    fn test_filename_md5_prefix() {
-        let prefix = filename_md5_prefix("myfile.txt");
-        assert_eq!(prefix.len(), 5);
-        assert!(prefix.chars().all(|c| c.is_ascii_hexdigit()));
+    fn test_generate_group_id_shape() {
+        let id = generate_group_id();
+        assert_eq!(id.len(), 16);
+        assert!(id.chars().all(|c| c.is_ascii_hexdigit()));
     }
 
     #[test]
-    fn test_parse_split_filename() {
-        let fnmd5 = filename_md5_prefix("myfile.txt");
-        let name = format!("/tmp/{}.SPLTD.abcd1234.aaaa.myfile.txt.cokacenc", fnmd5);
-        let path = PathBuf::from(&name);
-        let info = parse_enc_filename(&path).unwrap();
-        assert_eq!(info.original_name, "myfile.txt");
-        assert!(info.is_split);
-        assert_eq!(info.md5_fragment, "abcd1234");
-        assert_eq!(info.seq_index, Some(0));
+    fn test_key_prefix_shape() {
+        let kp = key_prefix(b"some-password");
+        assert_eq!(kp.len(), 4);
+        assert!(kp.chars().all(|c| c.is_ascii_hexdigit()));
     }
 
     #[test]
-    fn test_parse_single_filename() {
-        let fnmd5 = filename_md5_prefix("myfile.txt");
-        let name = format!("/tmp/{}.abcd1234.myfile.txt.cokacenc", fnmd5);
-        let path = PathBuf::from(&name);
+    fn test_roundtrip_chunk_filename() {
+        let dir = Path::new("/tmp");
+        let kp = "abcd";
+        let group_id = "0123456789abcdef";
+        let path = chunk_filename(dir, kp, group_id, 0).unwrap();
         let info = parse_enc_filename(&path).unwrap();
-        assert_eq!(info.original_name, "myfile.txt");
-        assert!(!info.is_split);
-        assert_eq!(info.md5_fragment, "abcd1234");
-        assert_eq!(info.seq_index, None);
+        assert_eq!(info.key_prefix, kp);
+        assert_eq!(info.group_id, group_id);
+        assert_eq!(info.seq_index, 0);
     }
 
     #[test]
-    fn test_roundtrip_single_name() {
-        let dir = Path::new("/tmp");
-        let original = "my document.pdf";
-        let md5 = "abcdef0123456789abcdef0123456789";
-        let path = single_file_enc_name(dir, original, md5);
-        let info = parse_enc_filename(&path).unwrap();
-        assert_eq!(info.original_name, original);
-        assert_eq!(info.md5_fragment, &md5[..8]);
-        assert!(!info.is_split);
+    fn test_group_enc_files_sorts_by_seq() {
+        let dir = tempdir();
+        let kp = "abcd";
+        let group_id = "0123456789abcdef";
+        for seq in [2usize, 0, 1] {
+            let path = chunk_filename(&dir, kp, group_id, seq).unwrap();
+            std::fs::write(&path, b"x").unwrap();
+        }
+
+        let groups = group_enc_files(&dir).unwrap();
+        let chunks = groups.get(group_id).unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks.iter().map(|c| c.seq_index).collect::<Vec<_>>(), vec![0, 1, 2]);
     }
 
-    #[test]
-    fn test_roundtrip_split_name() {
-        let dir = Path::new("/tmp");
-        let original = "archive.tar.gz";
-        let md5 = "abcdef0123456789abcdef0123456789";
-        let path = final_chunk_name(dir, original, md5, 0).unwrap();
-        let info = parse_enc_filename(&path).unwrap();
-        assert_eq!(info.original_name, original);
-        assert_eq!(info.md5_fragment, &md5[..8]);
-        assert!(info.is_split);
-        assert_eq!(info.seq_index, Some(0));
+    fn tempdir() -> PathBuf {
+        let mut dir = std::env::temp_dir();
+        let unique = format!("cokacenc-naming-test-{}", generate_group_id());
+        dir.push(unique);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
     }
 }
@@ -1,18 +1,27 @@
 use std::fs::{self, File};
-use std::io::{BufReader, BufWriter, Read, Write};
-use std::path::Path;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
+use hmac::Mac;
 use md5::{Digest, Md5};
 use serde::{Serialize, Deserialize};
 
 use crate::crypto::{
-    derive_key, generate_iv, generate_salt, load_key_file, write_header, ChunkEncryptor,
+    derive_dek_subkeys, derive_key, generate_dek, generate_iv, generate_nonce, generate_salt,
+    load_key_file, wrap_dek, write_header, ChunkEncryptor, EncryptionType, HmacSha256,
 };
 use crate::error::CokacencError;
 use crate::naming;
+use crate::pool;
 
 const READ_BUF_SIZE: usize = 64 * 1024; // 64KB
 
+/// Current `ChunkMetadata.version`. Bumped from 2 to 3 when AEAD encryption types were
+/// added; the shape is unchanged, so a version-2 reader still round-trips version-3
+/// metadata and vice versa. The whole-file MD5 path keeps working for both.
+const METADATA_VERSION: u32 = 3;
+
 // ─── Chunk metadata (embedded inside each encrypted chunk) ─────────────
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -43,6 +52,7 @@ pub(crate) struct ChunkMetadata {
 
 // ─── File info gathered in first pass ──────────────────────────────────
 
+#[derive(Clone)]
 struct FileInfo {
     size: u64,
     md5: String,
@@ -92,12 +102,24 @@ fn gather_file_info(path: &Path, use_md5: bool) -> Result<FileInfo, CokacencErro
 /// Pack (encrypt + split) all eligible files in a directory.
 /// Uses 2-pass: first pass computes MD5+metadata, second pass encrypts.
 /// Each chunk embeds full metadata.
+///
+/// `jobs` bounds how many independent units of work run concurrently. Since
+/// multiple files and the independent chunks of one split file are both
+/// "independent work" here, the budget is split between the two axes rather
+/// than nested (which would let `jobs` threads each spawn `jobs` more): with
+/// more than one file queued, files run across up to `jobs` worker threads
+/// and each file's own chunks are written sequentially; with a single file,
+/// that file's chunks instead run across up to `jobs` worker threads. `jobs
+/// <= 1` is fully serial, identical to the pre-`--jobs` code path.
 pub fn pack_directory(
     dir: &Path,
     key_path: &Path,
     split_size_mb: u64,
     delete: bool,
     use_md5: bool,
+    encryption: EncryptionType,
+    recursive: bool,
+    jobs: usize,
 ) -> Result<(), CokacencError> {
     let password = load_key_file(key_path)?;
     let split_size = if split_size_mb == 0 {
@@ -106,32 +128,68 @@ pub fn pack_directory(
         split_size_mb * 1024 * 1024
     };
 
-    let mut entries: Vec<_> = fs::read_dir(dir)?
-        .filter_map(|e| e.ok())
-        .filter(|e| {
-            let path = e.path();
-            if !path.is_file() {
-                return false;
-            }
-            let name = e.file_name().to_string_lossy().to_string();
-            // Skip .cokacenc files, hidden files
-            !name.ends_with(naming::EXT) && !name.starts_with('.')
-        })
-        .collect();
+    let mut files = Vec::new();
+    collect_files(dir, dir, recursive, &mut files)?;
+    files.sort_by(|a, b| a.1.cmp(&b.1));
 
-    entries.sort_by_key(|e| e.file_name());
-
-    if entries.is_empty() {
+    if files.is_empty() {
         println!("No files to pack in {}", dir.display());
         return Ok(());
     }
 
-    for entry in &entries {
+    let file_jobs = if files.len() > 1 { jobs } else { 1 };
+    let chunk_jobs = if files.len() > 1 { 1 } else { jobs };
+
+    pool::parallel_for_each(file_jobs, files, |(path, rel_name)| {
+        println!("Packing: {}", rel_name);
+        pack_file(&path, &rel_name, dir, &password, split_size, delete, use_md5, encryption, chunk_jobs)?;
+        println!("  Done: {}", rel_name);
+        Ok(())
+    })
+}
+
+/// Collect eligible files under `dir`, paired with their path relative to
+/// `root` (using `/` as the separator regardless of platform, so the stored
+/// name round-trips the same way on unpack everywhere). With `recursive`,
+/// subdirectories are walked depth-first; otherwise only direct entries of
+/// `dir` are considered, matching the pre-recursive behavior.
+fn collect_files(
+    dir: &Path,
+    root: &Path,
+    recursive: bool,
+    out: &mut Vec<(PathBuf, String)>,
+) -> Result<(), CokacencError> {
+    let mut entries: Vec<_> = fs::read_dir(dir)?.filter_map(|e| e.ok()).collect();
+    entries.sort_by_key(|e| e.file_name());
+
+    for entry in entries {
         let path = entry.path();
         let name = entry.file_name().to_string_lossy().to_string();
-        println!("Packing: {}", name);
-        pack_file(&path, &name, dir, &password, split_size, delete, use_md5)?;
-        println!("  Done: {}", name);
+
+        // Skip hidden files/directories and already-encrypted chunks
+        if name.starts_with('.') {
+            continue;
+        }
+
+        if path.is_dir() {
+            if recursive {
+                collect_files(&path, root, recursive, out)?;
+            }
+            continue;
+        }
+
+        if !path.is_file() || name.ends_with(naming::EXT) {
+            continue;
+        }
+
+        let rel = path
+            .strip_prefix(root)
+            .unwrap_or(&path)
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+            .join("/");
+        out.push((path, rel));
     }
 
     Ok(())
@@ -139,7 +197,10 @@ pub fn pack_directory(
 
 /// Pack a single file using 2-pass approach.
 /// Pass 1: gather file info (MD5, size, mtime, permissions).
-/// Pass 2: encrypt with metadata embedded in each chunk.
+/// Pass 2: encrypt with metadata embedded in each chunk, across up to
+/// `chunk_jobs` worker threads (each chunk opens and seeks its own handle on
+/// `file_path`, since concurrent chunks can't share one sequential reader).
+#[allow(clippy::too_many_arguments)]
 fn pack_file(
     file_path: &Path,
     original_name: &str,
@@ -148,6 +209,8 @@ fn pack_file(
     split_size: u64,
     delete: bool,
     use_md5: bool,
+    encryption: EncryptionType,
+    chunk_jobs: usize,
 ) -> Result<(), CokacencError> {
     // ── Pass 1: gather info ──
     let info = gather_file_info(file_path, use_md5)?;
@@ -165,79 +228,29 @@ fn pack_file(
         ((info.size + split_size - 1) / split_size) as usize
     };
 
-    // ── Pass 2: encrypt ──
-    let file = File::open(file_path)?;
-    let mut reader = BufReader::new(file);
-    let mut read_buf = [0u8; READ_BUF_SIZE];
-    let mut created_chunks: Vec<std::path::PathBuf> = Vec::new();
-
-    let result = (|| -> Result<(), CokacencError> {
-        for chunk_idx in 0..total_chunks {
-            let chunk_offset = chunk_idx as u64 * split_size;
-            let chunk_data_size = if info.size == 0 {
-                0
-            } else {
-                split_size.min(info.size - chunk_offset)
-            };
-
-            let metadata = ChunkMetadata {
-                version: 2,
-                group_id: group_id.clone(),
-                filename: original_name.to_string(),
-                file_size: info.size,
-                file_md5: info.md5.clone(),
-                modified: info.modified,
-                permissions: info.permissions,
-                total_chunks,
-                chunk_index: chunk_idx,
-                chunk_offset,
-                chunk_data_size,
-            };
-
-            let chunk_path = naming::chunk_filename(out_dir, &kp, &group_id, chunk_idx)?;
-            let chunk_file = File::create(&chunk_path)?;
-            created_chunks.push(chunk_path);
-            let mut writer = BufWriter::new(chunk_file);
-
-            let salt = generate_salt();
-            let iv = generate_iv();
-            let key = derive_key(password, &salt);
-            write_header(&mut writer, &salt, &iv, original_name)?;
-
-            let mut enc = ChunkEncryptor::new(&key, &iv);
-
-            // Write metadata length + metadata into encrypted stream
-            let meta_bytes = serde_json::to_vec(&metadata)
-                .map_err(|e| CokacencError::Other(format!("JSON serialize: {}", e)))?;
-            let meta_len_bytes = (meta_bytes.len() as u32).to_le_bytes();
-
-            let encrypted = enc.update(&meta_len_bytes);
-            writer.write_all(encrypted)?;
-            let encrypted = enc.update(&meta_bytes);
-            writer.write_all(encrypted)?;
-
-            // Write file data portion
-            let mut remaining = chunk_data_size;
-            while remaining > 0 {
-                let to_read = (READ_BUF_SIZE as u64).min(remaining) as usize;
-                let n = reader.read(&mut read_buf[..to_read])?;
-                if n == 0 { break; }
-                let encrypted = enc.update(&read_buf[..n]);
-                writer.write_all(encrypted)?;
-                remaining -= n as u64;
-            }
-
-            let final_block = enc.finalize();
-            writer.write_all(&final_block)?;
-            writer.flush()?;
-        }
+    // One data encryption key (DEK) per file: every chunk's payload is sealed
+    // under this same key, while each chunk independently wraps it under a
+    // master key derived from the passphrase (see the envelope header format
+    // in `crypto`), so rotating the passphrase only ever requires rewrapping
+    // these small per-chunk blobs, never re-encrypting the payload.
+    let dek = generate_dek();
 
+    // ── Pass 2: encrypt ──
+    let created_chunks: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+    let chunk_indices: Vec<usize> = (0..total_chunks).collect();
+
+    let result = pool::parallel_for_each(chunk_jobs, chunk_indices, |chunk_idx| {
+        let chunk_path = pack_one_chunk(
+            file_path, original_name, out_dir, password, &kp, &group_id, &dek,
+            &info, chunk_idx, total_chunks, split_size, encryption,
+        )?;
+        created_chunks.lock().unwrap().push(chunk_path);
         Ok(())
-    })();
+    });
 
-    // On error, clean up any partial chunk files
+    // On error, clean up any chunk files that did get written
     if result.is_err() {
-        for path in &created_chunks {
+        for path in created_chunks.into_inner().unwrap() {
             let _ = fs::remove_file(path);
         }
         return result;
@@ -268,6 +281,181 @@ fn pack_file(
     result
 }
 
+/// Encrypt a single chunk of `file_path` (the byte range owned by
+/// `chunk_idx`) and write it out as a new chunk file. Self-contained so it
+/// can run concurrently with sibling chunks of the same file: on failure, it
+/// removes the (partial) chunk file it created before returning the error.
+#[allow(clippy::too_many_arguments)]
+fn pack_one_chunk(
+    file_path: &Path,
+    original_name: &str,
+    out_dir: &Path,
+    password: &[u8],
+    kp: &str,
+    group_id: &str,
+    dek: &[u8; 32],
+    info: &FileInfo,
+    chunk_idx: usize,
+    total_chunks: usize,
+    split_size: u64,
+    encryption: EncryptionType,
+) -> Result<PathBuf, CokacencError> {
+    let chunk_offset = chunk_idx as u64 * split_size;
+    let chunk_data_size = if info.size == 0 {
+        0
+    } else {
+        split_size.min(info.size - chunk_offset)
+    };
+
+    let chunk_path = naming::chunk_filename(out_dir, kp, group_id, chunk_idx)?;
+
+    let result = (|| -> Result<(), CokacencError> {
+        let metadata = ChunkMetadata {
+            version: METADATA_VERSION,
+            group_id: group_id.to_string(),
+            filename: original_name.to_string(),
+            file_size: info.size,
+            file_md5: info.md5.clone(),
+            modified: info.modified,
+            permissions: info.permissions,
+            total_chunks,
+            chunk_index: chunk_idx,
+            chunk_offset,
+            chunk_data_size,
+        };
+
+        let chunk_file = File::create(&chunk_path)?;
+        let mut writer = BufWriter::new(chunk_file);
+
+        // Wrap this chunk's copy of the file's DEK under a master key derived
+        // from the passphrase and a fresh salt, so `rekey` can later rewrap it
+        // for a new passphrase without touching the ciphertext.
+        let wrap_salt = generate_salt();
+        let master_key = derive_key(password, &wrap_salt);
+        let wrap_nonce = generate_nonce();
+        let wrapped_dek = wrap_dek(&master_key, &wrap_nonce, dek);
+
+        let meta_bytes = serde_json::to_vec(&metadata)
+            .map_err(|e| CokacencError::Other(format!("JSON serialize: {}", e)))?;
+        let meta_len_bytes = (meta_bytes.len() as u32).to_le_bytes();
+
+        // Present only for Cbc and Ctr: an encrypt-then-MAC layer over the header +
+        // the whole ciphertext, since CBC and CTR (unlike the AEAD modes) have no
+        // authentication of their own.
+        let mut mac: Option<HmacSha256> = None;
+
+        let mut enc = match encryption {
+            EncryptionType::Cbc => {
+                let iv = generate_iv();
+                let (aes_key, mac_key) = derive_dek_subkeys(dek);
+                let header_bytes = write_header(
+                    &mut writer,
+                    EncryptionType::Cbc,
+                    &iv,
+                    None,
+                    original_name,
+                    &wrap_salt,
+                    &wrap_nonce,
+                    &wrapped_dek,
+                )?;
+                let mut m = HmacSha256::new_from_slice(&mac_key).expect("HMAC accepts a key of any length");
+                m.update(&header_bytes);
+                mac = Some(m);
+                ChunkEncryptor::new_cbc(&aes_key, &iv)
+            }
+            EncryptionType::Ctr => {
+                // Reuse the same 16-byte random generator as Cbc's IV: here it's
+                // the initial CTR counter block instead, but both just need a
+                // fresh random 128 bits per chunk.
+                let iv = generate_iv();
+                let (aes_key, mac_key) = derive_dek_subkeys(dek);
+                let header_bytes = write_header(
+                    &mut writer,
+                    EncryptionType::Ctr,
+                    &iv,
+                    None,
+                    original_name,
+                    &wrap_salt,
+                    &wrap_nonce,
+                    &wrapped_dek,
+                )?;
+                let mut m = HmacSha256::new_from_slice(&mac_key).expect("HMAC accepts a key of any length");
+                m.update(&header_bytes);
+                mac = Some(m);
+                ChunkEncryptor::new_ctr(&aes_key, &iv)
+            }
+            EncryptionType::AesGcm256 | EncryptionType::ChaCha20Poly1305 => {
+                let nonce = generate_nonce();
+                let mut nonce_field = [0u8; 16];
+                nonce_field[..12].copy_from_slice(&nonce);
+                // Ciphertext length equals plaintext length for these stream-based
+                // AEAD ciphers, so the frame length is known before sealing it.
+                let meta_frame_len = (meta_len_bytes.len() + meta_bytes.len()) as u32;
+                let header_bytes = write_header(
+                    &mut writer,
+                    encryption,
+                    &nonce_field,
+                    Some(meta_frame_len),
+                    original_name,
+                    &wrap_salt,
+                    &wrap_nonce,
+                    &wrapped_dek,
+                )?;
+                ChunkEncryptor::new_aead(encryption, dek, nonce, header_bytes)
+            }
+        };
+
+        // Seal metadata length + metadata as the chunk's first authenticated frame.
+        let (sealed_meta, _) = enc.seal_metadata(&meta_len_bytes, &meta_bytes);
+        writer.write_all(&sealed_meta)?;
+        if let Some(m) = mac.as_mut() {
+            m.update(&sealed_meta);
+        }
+
+        // Write this chunk's slice of the file data, read through its own
+        // handle on the source file so sibling chunks can run concurrently.
+        let mut remaining = chunk_data_size;
+        if remaining > 0 {
+            let mut source = File::open(file_path)?;
+            source.seek(SeekFrom::Start(chunk_offset))?;
+            let mut reader = BufReader::new(source);
+            let mut read_buf = [0u8; READ_BUF_SIZE];
+            while remaining > 0 {
+                let to_read = (READ_BUF_SIZE as u64).min(remaining) as usize;
+                let n = reader.read(&mut read_buf[..to_read])?;
+                if n == 0 { break; }
+                let encrypted = enc.update(&read_buf[..n]);
+                writer.write_all(&encrypted)?;
+                if let Some(m) = mac.as_mut() {
+                    m.update(&encrypted);
+                }
+                remaining -= n as u64;
+            }
+        }
+
+        let final_block = enc.finalize();
+        writer.write_all(&final_block)?;
+        if let Some(m) = mac.as_mut() {
+            m.update(&final_block);
+        }
+
+        if let Some(m) = mac {
+            let tag = m.finalize().into_bytes();
+            writer.write_all(&tag)?;
+        }
+        writer.flush()?;
+
+        Ok(())
+    })();
+
+    if let Err(e) = result {
+        let _ = fs::remove_file(&chunk_path);
+        return Err(e);
+    }
+
+    Ok(chunk_path)
+}
+
 fn format_size(bytes: u64) -> String {
     if bytes >= 1024 * 1024 * 1024 {
         format!("{:.1} GB", bytes as f64 / (1024.0 * 1024.0 * 1024.0))
@@ -0,0 +1,52 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use crate::error::CokacencError;
+
+/// Run `f` over `items`, distributing them across up to `jobs` worker threads.
+///
+/// `jobs <= 1` (or a single item) runs everything on the calling thread in
+/// order, so `--jobs 1` behaves exactly like the original serial code path.
+/// Otherwise, workers pull items off a shared queue until it's empty; a
+/// failing item does not stop other workers from draining the rest of the
+/// queue, so independent work already in flight isn't abandoned because of
+/// one bad unit. The first error encountered (if any) is returned once every
+/// worker has finished.
+pub fn parallel_for_each<T, F>(jobs: usize, items: Vec<T>, f: F) -> Result<(), CokacencError>
+where
+    T: Send,
+    F: Fn(T) -> Result<(), CokacencError> + Sync,
+{
+    if jobs <= 1 || items.len() <= 1 {
+        for item in items {
+            f(item)?;
+        }
+        return Ok(());
+    }
+
+    let queue: Mutex<VecDeque<T>> = Mutex::new(items.into_iter().collect());
+    let first_error: Mutex<Option<CokacencError>> = Mutex::new(None);
+    let worker_count = jobs;
+
+    std::thread::scope(|scope| {
+        for _ in 0..worker_count {
+            scope.spawn(|| loop {
+                let item = match queue.lock().unwrap().pop_front() {
+                    Some(item) => item,
+                    None => break,
+                };
+                if let Err(e) = f(item) {
+                    let mut slot = first_error.lock().unwrap();
+                    if slot.is_none() {
+                        *slot = Some(e);
+                    }
+                }
+            });
+        }
+    });
+
+    match first_error.into_inner().unwrap() {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
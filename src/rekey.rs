@@ -0,0 +1,234 @@
+use std::fs::OpenOptions;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use crate::crypto::{apply_rekey, load_key_file, read_header, unwrap_for_rekey, ChunkHeader};
+use crate::error::CokacencError;
+use crate::naming;
+
+/// Rotate the master passphrase for every `.cokacenc` file in a directory.
+///
+/// Each chunk's per-file data encryption key (DEK) is unwrapped with the old
+/// key file and re-wrapped with the new one; only the small wrap region of
+/// the chunk header is rewritten, so rotating the passphrase is O(number of
+/// chunks) rather than a full decrypt/re-encrypt of the payload.
+///
+/// Chunks written in a legacy (pre-envelope) format have no wrap region to
+/// rotate and are left untouched; they are still readable with the old key
+/// file until repacked.
+///
+/// Runs in two passes so a wrong `--old-key` or one corrupt chunk can't split
+/// a group across old/new master keys (which would leave it unreadable with
+/// either key): pass 1 unwraps every chunk's DEK under the old key *without
+/// writing anything*, and only if every chunk in the directory validates does
+/// pass 2 rewrap and write each chunk's wrap region. This does not make the
+/// write phase itself atomic — a mid-pass-2 I/O failure (e.g. disk full) can
+/// still leave some chunks rewrapped and others not, the same residual risk
+/// any multi-file filesystem operation has.
+pub fn rekey_directory(dir: &Path, old_key_path: &Path, new_key_path: &Path) -> Result<(), CokacencError> {
+    let old_password = load_key_file(old_key_path)?;
+    let new_password = load_key_file(new_key_path)?;
+    let groups = naming::group_enc_files(dir)?;
+
+    if groups.is_empty() {
+        println!("No .cokacenc files found in {}", dir.display());
+        return Ok(());
+    }
+
+    // ── Pass 1: validate every chunk unwraps under the old key before writing anything ──
+    struct Validated {
+        path: PathBuf,
+        header: ChunkHeader,
+        dek: Option<[u8; 32]>,
+    }
+    let mut validated = Vec::new();
+
+    for chunks in groups.values() {
+        for chunk_info in chunks {
+            let file = OpenOptions::new().read(true).open(&chunk_info.path)?;
+            let mut reader = BufReader::new(&file);
+            let header = read_header(&mut reader)?;
+            let dek = unwrap_for_rekey(&header, &old_password)?;
+            validated.push(Validated { path: chunk_info.path.clone(), header, dek });
+        }
+    }
+
+    // ── Pass 2: every chunk validated, now actually rewrap and write ──
+    let mut rekeyed = 0usize;
+    let mut skipped = 0usize;
+
+    for v in &validated {
+        match v.dek {
+            Some(dek) => {
+                let mut file = OpenOptions::new().read(true).write(true).open(&v.path)?;
+                apply_rekey(&mut file, &v.header, &dek, &new_password)?;
+                rekeyed += 1;
+            }
+            None => {
+                println!(
+                    "  Skipping {} (pre-envelope format, not rekeyable)",
+                    v.path.display(),
+                );
+                skipped += 1;
+            }
+        }
+    }
+
+    println!("Rekeyed {} chunk(s), skipped {} legacy chunk(s)", rekeyed, skipped);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{
+        decrypt_chunk_streaming, derive_dek_subkeys, derive_key, generate_dek, generate_iv,
+        generate_nonce, generate_salt, resolve_chunk_key, verify_chunk_hmac, wrap_dek,
+        write_header, ChunkEncryptor, EncryptionType, HmacSha256,
+    };
+    use hmac::Mac;
+
+    /// Build a single-chunk v5 (Cbc) group wrapped under `password`, write it to
+    /// `dir` under its proper `naming::chunk_filename`, and return its group id,
+    /// exactly like `pack::pack_one_chunk` does for one chunk.
+    fn write_test_chunk(dir: &Path, password: &[u8], plaintext_data: &[u8]) -> String {
+        let dek = generate_dek();
+        let (aes_key, mac_key) = derive_dek_subkeys(&dek);
+        let iv = generate_iv();
+        let wrap_salt = generate_salt();
+        let master_key = derive_key(password, &wrap_salt);
+        let wrap_nonce = generate_nonce();
+        let wrapped_dek = wrap_dek(&master_key, &wrap_nonce, &dek);
+
+        let mut chunk = Vec::new();
+        let header_bytes = write_header(
+            &mut chunk,
+            EncryptionType::Cbc,
+            &iv,
+            None,
+            "example.bin",
+            &wrap_salt,
+            &wrap_nonce,
+            &wrapped_dek,
+        )
+        .unwrap();
+
+        let mut mac = HmacSha256::new_from_slice(&mac_key).unwrap();
+        mac.update(&header_bytes);
+
+        let meta_bytes = br#"{"some":"metadata"}"#.to_vec();
+        let meta_len_bytes = (meta_bytes.len() as u32).to_le_bytes();
+
+        let mut enc = ChunkEncryptor::new_cbc(&aes_key, &iv);
+        let (sealed_meta, _) = enc.seal_metadata(&meta_len_bytes, &meta_bytes);
+        chunk.extend_from_slice(&sealed_meta);
+        mac.update(&sealed_meta);
+
+        let encrypted_data = enc.update(plaintext_data);
+        chunk.extend_from_slice(&encrypted_data);
+        mac.update(&encrypted_data);
+
+        let final_block = enc.finalize();
+        chunk.extend_from_slice(&final_block);
+        mac.update(&final_block);
+
+        let tag = mac.finalize().into_bytes();
+        chunk.extend_from_slice(&tag);
+
+        let group_id = naming::generate_group_id();
+        let key_prefix = naming::key_prefix(password);
+        let path = naming::chunk_filename(dir, &key_prefix, &group_id, 0).unwrap();
+        std::fs::write(&path, &chunk).unwrap();
+        group_id
+    }
+
+    fn tempdir() -> PathBuf {
+        let mut dir = std::env::temp_dir();
+        let unique = format!("cokacenc-rekey-test-{}", naming::generate_group_id());
+        dir.push(unique);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_key_file(contents: &str) -> PathBuf {
+        let mut path = std::env::temp_dir();
+        path.push(format!("cokacenc-rekey-test-key-{}", naming::generate_group_id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    /// Rekey a packed chunk and confirm it unpacks under the new key: the DEK
+    /// (and therefore the content/MAC keys derived from it) must be unchanged,
+    /// only the wrap region is rewritten.
+    #[test]
+    fn test_rekey_round_trip_unpacks_with_new_key() {
+        let dir = tempdir();
+        let old_key = write_key_file("old-password");
+        let new_key = write_key_file("new-password");
+        let old_password = load_key_file(&old_key).unwrap();
+        let new_password = load_key_file(&new_key).unwrap();
+        let plaintext_data = b"the quick brown fox jumps over the lazy dog, 0123456789".to_vec();
+
+        write_test_chunk(&dir, &old_password, &plaintext_data);
+
+        rekey_directory(&dir, &old_key, &new_key).unwrap();
+
+        let groups = naming::group_enc_files(&dir).unwrap();
+        let chunk = &groups.values().next().unwrap()[0];
+        let mut file = std::fs::File::open(&chunk.path).unwrap();
+        let header = read_header(&mut file).unwrap();
+
+        // Old password no longer unwraps the chunk's DEK.
+        assert!(resolve_chunk_key(&old_password, &header).is_err());
+
+        // New password does, and still recovers the original plaintext.
+        let (aes_key, mac_key) = resolve_chunk_key(&new_password, &header).unwrap();
+        let mac_key = mac_key.expect("Cbc chunks carry an HMAC key");
+        verify_chunk_hmac(&chunk.path, &header, &mac_key).unwrap();
+
+        let total_len = std::fs::metadata(&chunk.path).unwrap().len();
+        let mut data_file = std::fs::File::open(&chunk.path).unwrap();
+        let _ = read_header(&mut data_file).unwrap();
+        let mut decrypted = Vec::new();
+        decrypt_chunk_streaming(&mut data_file, &mut decrypted, &aes_key, &header, total_len).unwrap();
+        assert!(decrypted.ends_with(&plaintext_data));
+
+        let _ = std::fs::remove_dir_all(&dir);
+        let _ = std::fs::remove_file(&old_key);
+        let _ = std::fs::remove_file(&new_key);
+    }
+
+    /// If any chunk in the directory fails to validate under `--old-key`, the
+    /// two-pass split (fix 48fcef4) must leave every other chunk's wrap region
+    /// untouched rather than partially rewrapping the run.
+    #[test]
+    fn test_rekey_leaves_chunks_untouched_when_one_chunk_fails_validation() {
+        let dir = tempdir();
+        let old_key = write_key_file("old-password");
+        let new_key = write_key_file("new-password");
+        let old_password = load_key_file(&old_key).unwrap();
+
+        write_test_chunk(&dir, &old_password, b"good chunk data");
+        // Wrapped under a different password, so it won't unwrap under --old-key.
+        write_test_chunk(&dir, b"a-completely-different-password", b"bad chunk data");
+
+        let good_groups_before = naming::group_enc_files(&dir).unwrap();
+        let mut good_bytes_before = Vec::new();
+        for chunks in good_groups_before.values() {
+            for c in chunks {
+                good_bytes_before.push((c.path.clone(), std::fs::read(&c.path).unwrap()));
+            }
+        }
+
+        assert!(rekey_directory(&dir, &old_key, &new_key).is_err());
+
+        for (path, before) in good_bytes_before {
+            let after = std::fs::read(&path).unwrap();
+            assert_eq!(before, after, "no chunk should be rewritten when another chunk fails validation");
+        }
+
+        let _ = std::fs::remove_dir_all(&dir);
+        let _ = std::fs::remove_file(&old_key);
+        let _ = std::fs::remove_file(&new_key);
+    }
+}
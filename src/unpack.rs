@@ -1,13 +1,15 @@
+use std::collections::HashMap;
 use std::fs::{self, File};
 use std::io::{BufReader, BufWriter, Write};
 use std::path::Path;
 
 use md5::{Digest, Md5};
 
-use crate::crypto::{decrypt_chunk_streaming, derive_key, load_key_file, read_header};
+use crate::crypto::{decrypt_chunk_streaming, load_key_file, read_header, resolve_chunk_key, verify_chunk_hmac};
 use crate::error::CokacencError;
 use crate::naming;
 use crate::pack::ChunkMetadata;
+use crate::pool;
 
 // ─── MetadataSplitWriter (extracts metadata from decrypted stream) ─────
 
@@ -118,38 +120,57 @@ impl<W: Write> Write for TeeWriter<'_, W> {
     }
 }
 
+/// Reject a metadata-supplied relative path that could escape `--dir`, such
+/// as an absolute path or one containing a `..` component. Metadata is
+/// attacker-controlled the moment a chunk file is, so this is checked before
+/// the path is ever joined onto `dir` and used to create directories or files.
+fn is_safe_relative_path(name: &str) -> bool {
+    if name.is_empty() {
+        return false;
+    }
+    let path = Path::new(name);
+    path.is_relative() && path.components().all(|c| matches!(c, std::path::Component::Normal(_)))
+}
+
 // ─── Unpack (decrypt) ──────────────────────────────────────────────────
 
 /// Unpack (decrypt) all .cokacenc file groups in a directory.
 /// Metadata is extracted from each chunk. After decryption, .cokacenc files are deleted if requested.
-pub fn unpack_directory(dir: &Path, key_path: &Path, delete: bool) -> Result<(), CokacencError> {
+///
+/// `jobs` bounds how many independent original-file groups are decrypted and
+/// merged concurrently; chunks within one group always stay ordered by
+/// `seq_index` (see `unpack_file_group`). `jobs <= 1` is fully serial,
+/// identical to the pre-`--jobs` code path, including that a failing group
+/// stops the whole run; with `jobs > 1`, groups already running when one
+/// fails are allowed to finish rather than aborted mid-write, and the first
+/// error encountered across all groups is reported.
+pub fn unpack_directory(dir: &Path, key_path: &Path, delete: bool, jobs: usize) -> Result<(), CokacencError> {
     let password = load_key_file(key_path)?;
-    let groups = naming::group_enc_files(dir)?;
+    let groups: Vec<_> = naming::group_enc_files(dir)?.into_iter().collect();
 
     if groups.is_empty() {
         println!("No .cokacenc files found in {}", dir.display());
         return Ok(());
     }
 
-    for (group_id, chunks) in &groups {
+    pool::parallel_for_each(jobs, groups, |(group_id, chunks)| {
         println!("Unpacking: group {}... ({} chunk(s))", &group_id[..8.min(group_id.len())], chunks.len());
-        match unpack_file_group(dir, chunks, &password) {
+        match unpack_file_group(dir, &chunks, &password) {
             Ok(original_name) => {
                 if delete {
-                    for chunk_info in chunks {
+                    for chunk_info in &chunks {
                         let _ = fs::remove_file(&chunk_info.path);
                     }
                 }
                 println!("  Done: {}", original_name);
+                Ok(())
             }
             Err(e) => {
                 eprintln!("  Error (group {}): {}", group_id, e);
-                return Err(e);
+                Err(e)
             }
         }
-    }
-
-    Ok(())
+    })
 }
 
 /// Decrypt and merge a group of chunk files into the original file.
@@ -188,8 +209,16 @@ fn unpack_file_group(
         let enc_file = File::open(&chunk_info.path)?;
         let mut reader = BufReader::new(enc_file);
 
-        let (salt, iv, _header_filename) = read_header(&mut reader)?;
-        let key = derive_key(password, &salt);
+        let header = read_header(&mut reader)?;
+
+        // For Cbc chunks, recompute and compare the HMAC tag *before* decrypting
+        // anything, so a corrupt or forged chunk fails fast instead of only after
+        // the whole file has been written and MD5-checked.
+        let (key, mac_key) = resolve_chunk_key(password, &header)?;
+        if let Some(mac_key) = mac_key {
+            verify_chunk_hmac(&chunk_info.path, &header, &mac_key)?;
+        }
+        let total_len = fs::metadata(&chunk_info.path)?.len();
 
         // Decrypt through MetadataSplitWriter -> TeeWriter(file, md5)
         let meta_bytes;
@@ -199,7 +228,7 @@ fn unpack_file_group(
                 hasher: &mut md5_hasher,
             };
             let mut split = MetadataSplitWriter::new(&mut tee);
-            decrypt_chunk_streaming(&mut reader, &mut split, &key, &iv)?;
+            decrypt_chunk_streaming(&mut reader, &mut split, &key, &header, total_len)?;
             meta_bytes = split.take_metadata_bytes()?;
         }
 
@@ -215,6 +244,12 @@ fn unpack_file_group(
         }
 
         if i == 0 {
+            if !is_safe_relative_path(&meta.filename) {
+                let _ = fs::remove_file(&temp_path);
+                return Err(CokacencError::MetadataParse(format!(
+                    "Unsafe file path in metadata: {}", meta.filename
+                )));
+            }
             original_name = meta.filename.clone();
             expected_md5 = meta.file_md5.clone();
             file_size = meta.file_size;
@@ -260,8 +295,12 @@ fn unpack_file_group(
         ));
     }
 
-    // Rename to original filename
+    // Rename to original filename, recreating any subdirectories the name
+    // (from --recursive pack) may carry
     let out_path = dir.join(&original_name);
+    if let Some(parent) = out_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
     fs::rename(&temp_path, &out_path)?;
 
     // Restore permissions
@@ -290,3 +329,207 @@ fn unpack_file_group(
 
     Ok(original_name)
 }
+
+/// Decrypt and parse just one chunk's embedded metadata (HMAC/AEAD-verified
+/// first, same as everywhere else), without writing any file data anywhere.
+///
+/// Used by `extract` to find which group owns a given original filename
+/// before doing any range-restricted decryption: the on-disk chunk filename
+/// carries no filename information, so the only way to answer "which group is
+/// `name`?" is to decrypt each candidate's metadata.
+pub(crate) fn read_chunk_metadata(path: &Path, password: &[u8]) -> Result<ChunkMetadata, CokacencError> {
+    let enc_file = File::open(path)?;
+    let mut reader = BufReader::new(enc_file);
+    let header = read_header(&mut reader)?;
+
+    let (key, mac_key) = resolve_chunk_key(password, &header)?;
+    if let Some(mac_key) = mac_key {
+        verify_chunk_hmac(path, &header, &mac_key)?;
+    }
+    let total_len = fs::metadata(path)?.len();
+
+    let meta_bytes;
+    {
+        let mut sink = std::io::sink();
+        let mut split = MetadataSplitWriter::new(&mut sink);
+        decrypt_chunk_streaming(&mut reader, &mut split, &key, &header, total_len)?;
+        meta_bytes = split.take_metadata_bytes()?;
+    }
+
+    serde_json::from_slice(&meta_bytes).map_err(|e| CokacencError::MetadataParse(e.to_string()))
+}
+
+// ─── Verify (audit, never writes output) ───────────────────────────────
+
+/// Audit every `.cokacenc` group in a directory without extracting anything.
+///
+/// Unlike `unpack_directory`, a failing group does not stop the run: every
+/// group is checked and reported so a single pass surfaces every problem,
+/// per-chunk (missing sequence numbers, HMAC/AEAD authentication failures,
+/// metadata inconsistencies) and group-level (whole-file MD5 mismatch).
+pub fn verify_directory(dir: &Path, key_path: &Path) -> Result<(), CokacencError> {
+    let password = load_key_file(key_path)?;
+    let groups = naming::group_enc_files(dir)?;
+
+    if groups.is_empty() {
+        println!("No .cokacenc files found in {}", dir.display());
+        return Ok(());
+    }
+
+    let mut ok_groups = 0usize;
+    let total_groups = groups.len();
+
+    for (group_id, chunks) in &groups {
+        println!("Group {}:", &group_id[..8.min(group_id.len())]);
+        if verify_file_group(chunks, &password) {
+            ok_groups += 1;
+        }
+        println!();
+    }
+
+    println!("Summary: {}/{} group(s) OK", ok_groups, total_groups);
+    if ok_groups != total_groups {
+        return Err(CokacencError::Other(format!(
+            "{} of {} group(s) failed verification",
+            total_groups - ok_groups,
+            total_groups
+        )));
+    }
+
+    Ok(())
+}
+
+/// Verify one original-file group (all its chunks). Returns whether the whole
+/// group passed. Never returns early on the first problem: every chunk in the
+/// group's expected sequence range (0..=max seq_index present) is checked.
+fn verify_file_group(chunks: &[naming::EncFileInfo], password: &[u8]) -> bool {
+    let present: HashMap<usize, &naming::EncFileInfo> =
+        chunks.iter().map(|c| (c.seq_index, c)).collect();
+    let max_index = chunks.iter().map(|c| c.seq_index).max().unwrap_or(0);
+
+    let mut md5_hasher = Md5::new();
+    let mut original_name = String::new();
+    let mut expected_md5 = String::new();
+    let mut declared_total_chunks: Option<usize> = None;
+    let mut all_chunks_ok = true;
+
+    for seq in 0..=max_index {
+        let label = naming::seq_label(seq).unwrap_or_else(|_| format!("#{}", seq));
+        match present.get(&seq) {
+            None => {
+                println!("  [{}] MISSING", label);
+                all_chunks_ok = false;
+            }
+            Some(chunk_info) => {
+                match verify_one_chunk(
+                    chunk_info,
+                    password,
+                    seq,
+                    &mut md5_hasher,
+                    &mut original_name,
+                    &mut expected_md5,
+                    &mut declared_total_chunks,
+                ) {
+                    Ok(()) => println!("  [{}] PASS", label),
+                    Err(e) => {
+                        println!("  [{}] FAIL: {}", label, e);
+                        all_chunks_ok = false;
+                    }
+                }
+            }
+        }
+    }
+
+    // Exactly one terminal chunk: the chunk count declared in metadata (by the
+    // last chunk that verified) must equal the highest sequence number found + 1.
+    if let Some(total) = declared_total_chunks {
+        if total != max_index + 1 {
+            println!(
+                "  Chunk count mismatch: metadata declares {} chunk(s), {} found on disk",
+                total,
+                max_index + 1
+            );
+            all_chunks_ok = false;
+        }
+    }
+
+    let mut group_ok = all_chunks_ok;
+
+    if all_chunks_ok {
+        let md5_hex = format!("{:032x}", md5_hasher.finalize());
+        if expected_md5.is_empty() {
+            println!("  MD5 verification: skipped (not embedded)");
+        } else if md5_hex == expected_md5 {
+            println!("  MD5 verified: {}", md5_hex);
+        } else {
+            println!("  MD5 mismatch: expected {}, got {}", expected_md5, md5_hex);
+            group_ok = false;
+        }
+        println!("  File: {}", original_name);
+    }
+
+    println!("  Result: {}", if group_ok { "OK" } else { "FAILED" });
+    group_ok
+}
+
+/// Decrypt (and HMAC-verify, where applicable) a single chunk's metadata and
+/// payload without writing any output file, feeding its plaintext file data
+/// into the group's running MD5 hash.
+fn verify_one_chunk(
+    chunk_info: &naming::EncFileInfo,
+    password: &[u8],
+    expected_index: usize,
+    md5_hasher: &mut Md5,
+    original_name: &mut String,
+    expected_md5: &mut String,
+    declared_total_chunks: &mut Option<usize>,
+) -> Result<(), CokacencError> {
+    let enc_file = File::open(&chunk_info.path)?;
+    let mut reader = BufReader::new(enc_file);
+    let header = read_header(&mut reader)?;
+
+    let (key, mac_key) = resolve_chunk_key(password, &header)?;
+    if let Some(mac_key) = mac_key {
+        verify_chunk_hmac(&chunk_info.path, &header, &mac_key)?;
+    }
+    let total_len = fs::metadata(&chunk_info.path)?.len();
+
+    let meta_bytes;
+    {
+        let mut sink = std::io::sink();
+        let mut tee = TeeWriter {
+            file: &mut sink,
+            hasher: md5_hasher,
+        };
+        let mut split = MetadataSplitWriter::new(&mut tee);
+        decrypt_chunk_streaming(&mut reader, &mut split, &key, &header, total_len)?;
+        meta_bytes = split.take_metadata_bytes()?;
+    }
+
+    let meta: ChunkMetadata = serde_json::from_slice(&meta_bytes)
+        .map_err(|e| CokacencError::MetadataParse(e.to_string()))?;
+
+    if meta.chunk_index != expected_index {
+        return Err(CokacencError::MetadataParse(format!(
+            "Chunk index mismatch: expected {}, got {}",
+            expected_index, meta.chunk_index
+        )));
+    }
+
+    if expected_index == 0 {
+        if !is_safe_relative_path(&meta.filename) {
+            return Err(CokacencError::MetadataParse(format!(
+                "Unsafe file path in metadata: {}", meta.filename
+            )));
+        }
+        *original_name = meta.filename.clone();
+        *expected_md5 = meta.file_md5.clone();
+    } else if meta.filename != *original_name {
+        return Err(CokacencError::MetadataParse(
+            "Inconsistent filename across chunks".to_string(),
+        ));
+    }
+    *declared_total_chunks = Some(meta.total_chunks);
+
+    Ok(())
+}